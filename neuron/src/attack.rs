@@ -0,0 +1,60 @@
+//! Adversarial examples: inputs nudged in the direction that increases the loss the most.
+use crate::{
+    loss::{self, Reduction},
+    mlp::Mlp,
+    val::Val,
+};
+
+/// The fast gradient sign method: `x` with every feature moved by `epsilon` in the direction
+/// that increases the cross-entropy of `model`'s outputs with the class `y`.
+///
+/// Features whose gradient is zero are left as they are. The gradients of the model's
+/// parameters are not touched.
+pub fn fgsm(model: &Mlp, x: &[f64], y: usize, epsilon: f64) -> Vec<f64> {
+    let inputs: Vec<Val> = x.iter().map(|x| Val::from(*x)).collect();
+    let logits = model.forward_vals(&inputs);
+    let l = loss::cross_entropy(&[logits], &[y], Reduction::Sum).remove(0);
+    l.backward_wrt(&inputs);
+
+    let perturbed = x
+        .iter()
+        .zip(&inputs)
+        .map(|(x, input)| {
+            let gradient = input.gradient();
+            if gradient == 0.0 {
+                *x
+            } else {
+                x + epsilon * gradient.signum()
+            }
+        })
+        .collect();
+    l.free_graph();
+    perturbed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fgsm;
+    use crate::{
+        loss::{self, Reduction},
+        mlp::Mlp,
+        neuron::Activation,
+    };
+
+    #[test]
+    fn fgsm_increases_the_loss() {
+        // A single linear layer makes the cross-entropy convex in the input, so a step along the
+        // sign of the gradient can't decrease it.
+        let model = Mlp::new(3, vec![2]).with_output_activation(Activation::Linear);
+        let x = [0.5, -1.0, 2.0];
+        let loss =
+            |xs: &[f64]| loss::cross_entropy(&[model.forward(xs)], &[1], Reduction::Sum)[0].data();
+
+        let adversarial = fgsm(&model, &x, 1, 0.1);
+        for (a, x) in adversarial.iter().zip(&x) {
+            assert!(((a - x).abs() - 0.1).abs() < 1e-12);
+        }
+        assert!(loss(&adversarial) > loss(&x));
+        assert!(model.parameters().iter().all(|p| p.gradient() == 0.0));
+    }
+}
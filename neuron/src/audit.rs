@@ -0,0 +1,236 @@
+//! An opt-in, per-thread audit of the numerical stability of the operations of the graph.
+//!
+//! While enabled, every node created records the magnitudes of its inputs and result, and
+//! nodes prone to precision loss are flagged: sums of near-equal values of opposite signs
+//! (catastrophic cancellation), and non-finite or vanished results from finite, non-zero inputs
+//! (overflow, underflow and NaN).
+//!
+//! ```
+//! use neuron::{audit, val::Val};
+//!
+//! audit::enable();
+//! let a = Val::new(1.0e10, "a");
+//! let _ = a.clone() + -Val::new(1.0e10 + 1.0, "b");
+//! let report = audit::disable().unwrap();
+//! assert_eq!(report.findings().len(), 1);
+//! ```
+use std::{cell::RefCell, collections::BTreeMap, fmt::Display};
+
+thread_local! {
+    static AUDIT: RefCell<Option<Report>> = const { RefCell::new(None) };
+}
+
+/// Only the first findings are kept, the rest are only counted.
+const MAX_FINDINGS: usize = 100;
+
+/// A sum losing more than this fraction of the magnitude of its operands is flagged.
+const CANCELLATION_RATIO: f64 = 1e-8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Issue {
+    /// Most significant digits of the operands cancelled out.
+    Cancellation,
+    /// A finite input produced an infinite result.
+    Overflow,
+    /// Non-zero inputs produced a zero result where they shouldn't have.
+    Underflow,
+    /// Non-NaN inputs produced NaN.
+    NaN,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub issue: Issue,
+    pub op: String,
+    pub label: Option<String>,
+    pub inputs: Vec<f64>,
+    pub result: f64,
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} in {} {}(inputs: {:?}, result: {})",
+            self.issue,
+            self.op,
+            self.label
+                .as_ref()
+                .map_or(String::new(), |l| format!("'{l}' ")),
+            self.inputs,
+            self.result
+        )
+    }
+}
+
+/// Magnitudes seen for a single op type.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OpMagnitudes {
+    pub count: usize,
+    pub max_input: f64,
+    pub max_result: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    ops: BTreeMap<String, OpMagnitudes>,
+    findings: Vec<Finding>,
+    total_findings: usize,
+}
+
+impl Report {
+    pub fn ops(&self) -> &BTreeMap<String, OpMagnitudes> {
+        &self.ops
+    }
+
+    /// The first findings of the run.
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    /// How many nodes were flagged, including the ones not kept in [`Report::findings`].
+    pub fn total_findings(&self) -> usize {
+        self.total_findings
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:>10} {:>14} {:>14}",
+            "op", "count", "max |input|", "max |result|"
+        )?;
+        for (op, m) in &self.ops {
+            writeln!(
+                f,
+                "{:<12} {:>10} {:>14.3e} {:>14.3e}",
+                op, m.count, m.max_input, m.max_result
+            )?;
+        }
+
+        write!(f, "{} finding(s)", self.total_findings)?;
+        for finding in &self.findings {
+            write!(f, "\n  {finding}")?;
+        }
+        if self.total_findings > self.findings.len() {
+            write!(
+                f,
+                "\n  ... {} more",
+                self.total_findings - self.findings.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Start auditing, discarding anything recorded before.
+pub fn enable() {
+    AUDIT.with(|a| *a.borrow_mut() = Some(Report::default()));
+}
+
+/// Stop auditing and return the report.
+pub fn disable() -> Option<Report> {
+    AUDIT.with(|a| a.borrow_mut().take())
+}
+
+pub fn is_enabled() -> bool {
+    AUDIT.with(|a| a.borrow().is_some())
+}
+
+/// A copy of the report so far.
+pub fn snapshot() -> Option<Report> {
+    AUDIT.with(|a| a.borrow().clone())
+}
+
+pub fn print_report() {
+    match snapshot() {
+        Some(report) => println!("{report}"),
+        None => println!("audit is not enabled"),
+    }
+}
+
+fn issue(op: &str, inputs: &[f64], result: f64) -> Option<Issue> {
+    if inputs.iter().any(|x| x.is_nan()) {
+        return None;
+    }
+    if result.is_nan() {
+        return Some(Issue::NaN);
+    }
+    if inputs.iter().any(|x| x.is_infinite()) {
+        return None;
+    }
+    if result.is_infinite() {
+        return Some(Issue::Overflow);
+    }
+
+    let largest = inputs.iter().fold(0.0f64, |m, x| m.max(x.abs()));
+    match op {
+        "+" if result.abs() < CANCELLATION_RATIO * largest && result != 0.0 => {
+            Some(Issue::Cancellation)
+        }
+        "*" if result == 0.0 && inputs.iter().all(|x| *x != 0.0) => Some(Issue::Underflow),
+        _ => None,
+    }
+}
+
+/// Record a newly created node.
+pub(crate) fn record(op: &str, label: Option<&str>, inputs: &[f64], result: f64) {
+    AUDIT.with(|a| {
+        let mut audit = a.borrow_mut();
+        let Some(report) = audit.as_mut() else {
+            return;
+        };
+
+        let magnitudes = report.ops.entry(op.to_string()).or_default();
+        magnitudes.count += 1;
+        for x in inputs {
+            magnitudes.max_input = magnitudes.max_input.max(x.abs());
+        }
+        magnitudes.max_result = magnitudes.max_result.max(result.abs());
+
+        if let Some(issue) = issue(op, inputs, result) {
+            report.total_findings += 1;
+            if report.findings.len() < MAX_FINDINGS {
+                report.findings.push(Finding {
+                    issue,
+                    op: op.to_string(),
+                    label: label.map(str::to_string),
+                    inputs: inputs.to_vec(),
+                    result,
+                });
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Issue;
+    use crate::val::Val;
+
+    #[test]
+    fn flags_unstable_ops() {
+        super::enable();
+
+        let big = Val::new(1e300, "big");
+        let _ = big.clone() * big.clone();
+        let _ = Val::new(1.0, "a") + -Val::new(1.0 + 1e-12, "b");
+        let _ = Val::new(-2.0, "base").pow(&Val::from(0.5));
+        let _ = Val::new(1e-200, "tiny") * Val::from(1e-200);
+        let _ = Val::new(1.0, "a") + Val::new(2.0, "b");
+
+        let report = super::disable().unwrap();
+        let issues: Vec<Issue> = report.findings().iter().map(|f| f.issue).collect();
+        assert_eq!(
+            issues,
+            vec![
+                Issue::Overflow,
+                Issue::Cancellation,
+                Issue::NaN,
+                Issue::Underflow
+            ]
+        );
+        assert_eq!(report.ops()["*"].max_input, 1e300);
+    }
+}
@@ -0,0 +1,109 @@
+//! Autoencoders learning a compressed code of their inputs by reconstructing them.
+use crate::{
+    loss::{self, Reduction},
+    mlp::Mlp,
+    neuron::Activation,
+    optim::Sgd,
+    val::Val,
+};
+
+/// An encoder compressing its input into a small latent code and a mirrored decoder
+/// reconstructing the input from that code.
+pub struct Autoencoder {
+    encoder: Mlp,
+    decoder: Mlp,
+}
+
+impl Autoencoder {
+    /// `hidden_layers` are the sizes of the encoder layers between the input and the
+    /// bottleneck, the decoder uses them in reverse.
+    pub fn new(input_dim: usize, bottleneck: usize, hidden_layers: Vec<usize>) -> Self {
+        let mut encoder_layers = hidden_layers.clone();
+        encoder_layers.push(bottleneck);
+
+        let mut decoder_layers: Vec<usize> = hidden_layers.into_iter().rev().collect();
+        decoder_layers.push(input_dim);
+
+        Self {
+            encoder: Mlp::new(input_dim, encoder_layers).with_output_activation(Activation::Linear),
+            decoder: Mlp::new(bottleneck, decoder_layers)
+                .with_output_activation(Activation::Linear),
+        }
+    }
+
+    pub fn encode(&self, xs: &[f64]) -> Vec<Val> {
+        self.encoder.forward(xs)
+    }
+
+    pub fn decode(&self, code: &[Val]) -> Vec<Val> {
+        self.decoder.forward_vals(code)
+    }
+
+    pub fn forward(&self, xs: &[f64]) -> Vec<Val> {
+        self.decode(&self.encode(xs))
+    }
+
+    /// The values of the latent code of `xs`.
+    pub fn latent(&self, xs: &[f64]) -> Vec<f64> {
+        self.encode(xs).iter().map(Val::data).collect()
+    }
+
+    /// The mean squared error between `xs` and its reconstruction.
+    pub fn reconstruction_loss(&self, xs: &[f64]) -> Val {
+        loss::mse(&[self.forward(xs)], &[xs.to_vec()], Reduction::Mean).remove(0)
+    }
+
+    /// Train on the mean reconstruction loss of `data` with full batch gradient descent,
+    /// returning the loss of every epoch.
+    pub fn fit(&self, data: &[Vec<f64>], learning_rate: f64, epochs: usize) -> Vec<f64> {
+        let mut sgd = Sgd::new(self.parameters(), learning_rate);
+
+        (0..epochs)
+            .map(|_| {
+                let reconstructions: Vec<Vec<Val>> =
+                    data.iter().map(|xs| self.forward(xs)).collect();
+                let loss = loss::mse(&reconstructions, data, Reduction::Mean).remove(0);
+
+                sgd.zero_grad();
+                loss.back_prop_gradient();
+                sgd.step();
+                loss.free_graph();
+                loss.data()
+            })
+            .collect()
+    }
+
+    pub fn encoder(&self) -> &Mlp {
+        &self.encoder
+    }
+
+    pub fn decoder(&self) -> &Mlp {
+        &self.decoder
+    }
+
+    pub fn parameters(&self) -> Vec<Val> {
+        let mut params = self.encoder.parameters();
+        params.extend(self.decoder.parameters());
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Autoencoder;
+
+    #[test]
+    fn autoencoder() {
+        let ae = Autoencoder::new(4, 2, vec![3]);
+        assert_eq!(ae.latent(&[1.0, 0.0, 0.0, 1.0]).len(), 2);
+        assert_eq!(ae.forward(&[1.0, 0.0, 0.0, 1.0]).len(), 4);
+        assert_eq!(
+            ae.parameters().len(),
+            (4 * 3 + 3) + (3 * 2 + 2) + (2 * 3 + 3) + (3 * 4 + 4)
+        );
+
+        let data = vec![vec![1.0, 0.0, 0.0, 1.0], vec![0.0, 1.0, 1.0, 0.0]];
+        let losses = ae.fit(&data, 0.05, 50);
+        assert!(losses.last().unwrap() < losses.first().unwrap());
+    }
+}
@@ -0,0 +1,238 @@
+//! Hooks into the training loop of the estimators, see e.g. [`MlpClassifier::with_callback`].
+//!
+//! [`MlpClassifier::with_callback`]: crate::estimator::MlpClassifier::with_callback
+use std::{cell::RefCell, fs, path::PathBuf, rc::Rc};
+
+use crate::{
+    history::History,
+    mlp::{GradientNorms, Mlp},
+    val::Val,
+};
+
+pub trait Callback {
+    /// Called once before the first optimizer step.
+    fn on_train_begin(&mut self, _model: &Mlp) {}
+
+    /// Called after every backward pass, before the optimizer step, with the norms of the
+    /// gradients.
+    fn on_gradients(&mut self, _model: &Mlp, _norms: &GradientNorms) {}
+
+    /// Called after every optimizer step, `step` being the number of steps taken so far.
+    fn on_step(&mut self, model: &Mlp, step: usize);
+}
+
+/// Saves the model every `every` steps, e.g. at every trough of a [`CyclicCosine`] schedule
+/// for a snapshot ensemble, see [`Ensemble::from_snapshots`].
+///
+/// Clones share the list of saved paths, so one can be kept to read them after handing the
+/// callback to an estimator.
+///
+/// [`CyclicCosine`]: crate::schedule::CyclicCosine
+/// [`Ensemble::from_snapshots`]: crate::ensemble::Ensemble::from_snapshots
+#[derive(Clone, Debug)]
+pub struct Snapshots {
+    dir: PathBuf,
+    every: usize,
+    paths: Rc<RefCell<Vec<PathBuf>>>,
+}
+
+impl Snapshots {
+    /// Snapshots go to `snapshot-<step>.mlp` files in `dir`, which is created if needed.
+    pub fn new(dir: impl Into<PathBuf>, every: usize) -> Self {
+        assert!(every > 0, "snapshots need at least one step between them");
+        Self {
+            dir: dir.into(),
+            every,
+            paths: Rc::default(),
+        }
+    }
+
+    /// The files written so far, oldest first.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.paths.borrow().clone()
+    }
+}
+
+impl Callback for Snapshots {
+    /// Panics if the snapshot can't be written.
+    fn on_step(&mut self, model: &Mlp, step: usize) {
+        if !step.is_multiple_of(self.every) {
+            return;
+        }
+
+        let path = self.dir.join(format!("snapshot-{step}.mlp"));
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| model.save(&path))
+            .unwrap_or_else(|e| panic!("cannot save {}: {e}", path.display()));
+        self.paths.borrow_mut().push(path);
+    }
+}
+
+/// Records the global and per-layer L2 norms of the gradients of every step, see
+/// [`Mlp::gradient_norms`].
+///
+/// Clones share the recorded norms, like [`Snapshots`] share their paths.
+#[derive(Clone, Debug, Default)]
+pub struct GradientNormLog {
+    norms: Rc<RefCell<Vec<GradientNorms>>>,
+}
+
+impl GradientNormLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The norms of every step so far.
+    pub fn norms(&self) -> Vec<GradientNorms> {
+        self.norms.borrow().clone()
+    }
+
+    /// Append the norms of every step to `history`, as a `gradient norm` series for the global
+    /// norms and one `gradient norm <layer>` series per layer.
+    pub fn push_to(&self, history: &mut History) {
+        for norms in self.norms.borrow().iter() {
+            history.push("gradient norm", norms.global);
+            for (layer, norm) in norms.per_layer.iter().enumerate() {
+                history.push(&format!("gradient norm {layer}"), *norm);
+            }
+        }
+    }
+}
+
+impl Callback for GradientNormLog {
+    fn on_gradients(&mut self, _model: &Mlp, norms: &GradientNorms) {
+        self.norms.borrow_mut().push(norms.clone());
+    }
+
+    fn on_step(&mut self, _model: &Mlp, _step: usize) {}
+}
+
+/// Records the ratio of the L2 norm of the update of every layer to the norm of its parameters
+/// before the update, after every step. Ratios far from about `1e-3` usually mean a learning
+/// rate too high or too low for that layer.
+///
+/// Norms below `1e-12`, e.g. of a layer initialized to zero, are replaced by `1e-12`,
+/// so such a layer gets a large ratio rather than an infinite or NaN one.
+///
+/// Clones share the recorded ratios, like [`Snapshots`] share their paths.
+#[derive(Clone, Debug, Default)]
+pub struct UpdateRatios {
+    // The parameters of every layer before the next step.
+    previous: Vec<Vec<f64>>,
+    ratios: Rc<RefCell<Vec<Vec<f64>>>>,
+}
+
+impl UpdateRatios {
+    const EPS: f64 = 1e-12;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The ratio of every layer, input layer first, for every step so far.
+    pub fn ratios(&self) -> Vec<Vec<f64>> {
+        self.ratios.borrow().clone()
+    }
+
+    /// Append the ratios of every step to `history`, as one `update ratio <layer>` series per
+    /// layer.
+    pub fn push_to(&self, history: &mut History) {
+        for step in self.ratios.borrow().iter() {
+            for (layer, ratio) in step.iter().enumerate() {
+                history.push(&format!("update ratio {layer}"), *ratio);
+            }
+        }
+    }
+
+    fn parameters(model: &Mlp) -> Vec<Vec<f64>> {
+        model
+            .layers()
+            .iter()
+            .map(|l| l.parameters().iter().map(Val::data).collect())
+            .collect()
+    }
+}
+
+impl Callback for UpdateRatios {
+    fn on_train_begin(&mut self, model: &Mlp) {
+        self.previous = Self::parameters(model);
+    }
+
+    fn on_step(&mut self, model: &Mlp, _step: usize) {
+        let current = Self::parameters(model);
+        let ratios = current
+            .iter()
+            .zip(&self.previous)
+            .map(|(after, before)| {
+                let update = norm(after.iter().zip(before).map(|(a, b)| a - b));
+                update / norm(before.iter().copied()).max(Self::EPS)
+            })
+            .collect();
+        self.ratios.borrow_mut().push(ratios);
+        self.previous = current;
+    }
+}
+
+fn norm(xs: impl Iterator<Item = f64>) -> f64 {
+    xs.map(|x| x * x).sum::<f64>().sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Callback, GradientNormLog, UpdateRatios};
+    use crate::{estimator::MlpRegressor, history::History, mlp::Mlp};
+
+    #[test]
+    fn gradient_norms() {
+        let norms = GradientNormLog::new();
+        let mut regressor = MlpRegressor::new(vec![4])
+            .with_epochs(20)
+            .with_callback(norms.clone());
+        regressor.fit(&[vec![0.0], vec![1.0]], &[0.0, 1.0]);
+        assert_eq!(norms.norms().len(), 20);
+        assert!(norms.norms().iter().all(|n| n.per_layer.len() == 2));
+
+        let mut history = History::new();
+        norms.push_to(&mut history);
+        assert_eq!(history.series("gradient norm").unwrap().len(), 20);
+        assert_eq!(history.series("gradient norm 1").unwrap().len(), 20);
+        assert!(history.series("gradient norm").unwrap()[0] > 0.0);
+    }
+
+    #[test]
+    fn update_ratios() {
+        let model = Mlp::new(1, vec![1]);
+        let params = model.parameters();
+        params[0].set_data(3.0);
+        params[1].set_data(4.0);
+
+        let mut ratios = UpdateRatios::new();
+        ratios.on_train_begin(&model);
+        params[0].set_data(3.3);
+        params[1].set_data(3.6);
+        ratios.on_step(&model, 1);
+        assert!((ratios.ratios()[0][0] - 0.1).abs() < 1e-12);
+
+        // A layer starting at zero.
+        let mut ratios = UpdateRatios::new();
+        params[0].set_data(0.0);
+        params[1].set_data(0.0);
+        ratios.on_train_begin(&model);
+        ratios.on_step(&model, 1);
+        params[1].set_data(1e-15);
+        ratios.on_step(&model, 2);
+        assert_eq!(ratios.ratios(), [[0.0], [1e-3]]);
+
+        let ratios = UpdateRatios::new();
+        let mut regressor = MlpRegressor::new(vec![4])
+            .with_epochs(20)
+            .with_callback(ratios.clone());
+        regressor.fit(&[vec![0.0], vec![1.0]], &[0.0, 1.0]);
+        assert_eq!(ratios.ratios().len(), 20);
+        assert!(ratios.ratios().iter().flatten().all(|r| r.is_finite()));
+
+        let mut history = History::new();
+        ratios.push_to(&mut history);
+        assert_eq!(history.series("update ratio 1").unwrap().len(), 20);
+    }
+}
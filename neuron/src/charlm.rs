@@ -0,0 +1,169 @@
+//! A character-level language model: learns to predict the next character of a text, then
+//! writes more of it.
+//!
+//! ```no_run
+//! use neuron::charlm::CharLm;
+//!
+//! let text = std::fs::read_to_string("input.txt").unwrap();
+//! let model = CharLm::new(&text, 8, 32, 0);
+//! model.train(&text, 20, 25, 0.05);
+//! println!("{}", model.generate("The ", 100));
+//! ```
+use std::{fs, io, path::Path};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{decode, layer::Layer, neuron::Activation, optim::Sgd, sequence::Bptt, val::Val};
+
+/// An embedding per character, a recurrent layer over the embedding of the current character
+/// and the previous hidden state, and a linear head scoring every character of the vocabulary
+/// as the next one.
+pub struct CharLm {
+    vocab: Vec<char>,
+    embeddings: Vec<Vec<Val>>,
+    recurrent: Layer,
+    head: Layer,
+}
+
+impl CharLm {
+    /// A model over the characters appearing in `vocab`, e.g. the training text, with every
+    /// parameter drawn uniformly from `[-1, 1)` by an RNG seeded with `seed`.
+    pub fn new(vocab: &str, embedding_dim: usize, hidden_size: usize, seed: u64) -> Self {
+        let mut chars: Vec<char> = vocab.chars().collect();
+        chars.sort();
+        chars.dedup();
+        assert!(
+            !chars.is_empty(),
+            "the vocabulary needs at least one character"
+        );
+
+        let model = Self {
+            embeddings: chars
+                .iter()
+                .map(|_| (0..embedding_dim).map(|_| Val::from(0.0)).collect())
+                .collect(),
+            recurrent: Layer::new(embedding_dim + hidden_size, hidden_size)
+                .with_activation(Activation::Tanh),
+            head: Layer::new(hidden_size, chars.len()).with_activation(Activation::Linear),
+            vocab: chars,
+        };
+        let mut rng = StdRng::seed_from_u64(seed);
+        for p in model.parameters() {
+            p.set_data(rng.gen_range(-1.0..1.0));
+        }
+        model
+    }
+
+    pub fn vocab(&self) -> &[char] {
+        &self.vocab
+    }
+
+    fn token(&self, c: char) -> usize {
+        self.vocab
+            .binary_search(&c)
+            .unwrap_or_else(|_| panic!("'{c}' is not in the vocabulary"))
+    }
+
+    /// The logits of the character following `token`, and the next hidden state.
+    fn step(&self, token: usize, hidden: &[Val]) -> (Vec<Val>, Vec<Val>) {
+        let mut inputs = self.embeddings[token].clone();
+        inputs.extend_from_slice(hidden);
+        let hidden = self.recurrent.forward(&inputs);
+        (self.head.forward(&hidden), hidden)
+    }
+
+    fn initial_hidden(&self) -> Vec<Val> {
+        (0..self.recurrent.num_outputs())
+            .map(|_| Val::from(0.0))
+            .collect()
+    }
+
+    /// Train on predicting every character of `text` from the ones before it, with truncated
+    /// back propagation every `window` characters, and return the mean cross-entropy of every
+    /// epoch.
+    pub fn train(&self, text: &str, epochs: usize, window: usize, learning_rate: f64) -> Vec<f64> {
+        let tokens: Vec<usize> = text.chars().map(|c| self.token(c)).collect();
+        assert!(tokens.len() > 1, "the text needs at least two characters");
+
+        let mut sgd = Sgd::new(self.parameters(), learning_rate);
+        let mut bptt = Bptt::new(window);
+        (0..epochs)
+            .map(|_| {
+                bptt.train_tokens(&mut sgd, self.initial_hidden(), &tokens, |token, hidden| {
+                    self.step(token, hidden)
+                })
+            })
+            .collect()
+    }
+
+    /// Like [`CharLm::train`] on the contents of the file at `path`.
+    pub fn train_file(
+        &self,
+        path: impl AsRef<Path>,
+        epochs: usize,
+        window: usize,
+        learning_rate: f64,
+    ) -> io::Result<Vec<f64>> {
+        let text = fs::read_to_string(path)?;
+        Ok(self.train(&text, epochs, window, learning_rate))
+    }
+
+    /// `prompt` followed by the `n` most likely characters after it, picked one at a time.
+    pub fn generate(&self, prompt: &str, n: usize) -> String {
+        let tokens: Vec<usize> = prompt.chars().map(|c| self.token(c)).collect();
+        let (last, context) = tokens
+            .split_last()
+            .expect("the prompt needs at least one character");
+
+        // The decoder feeds the last character of the prompt itself. Nothing is trained here,
+        // so the hidden state is detached after every step to keep the graph from growing.
+        let mut hidden = self.initial_hidden();
+        for token in context {
+            hidden = self
+                .step(*token, &hidden)
+                .1
+                .iter()
+                .map(Val::detach)
+                .collect();
+        }
+
+        let generated = decode::greedy(
+            |token, hidden: &Vec<Val>| {
+                let (logits, hidden) = self.step(token, hidden);
+                let logits = logits.iter().map(Val::data).collect();
+                (logits, hidden.iter().map(Val::detach).collect())
+            },
+            hidden,
+            *last,
+            n,
+        );
+
+        let mut text = prompt.to_string();
+        text.extend(generated.iter().map(|t| self.vocab[*t]));
+        text
+    }
+
+    /// The embeddings, then the parameters of the recurrent layer and of the head.
+    pub fn parameters(&self) -> Vec<Val> {
+        let mut params: Vec<Val> = self.embeddings.iter().flatten().cloned().collect();
+        params.extend(self.recurrent.parameters());
+        params.extend(self.head.parameters());
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CharLm;
+
+    #[test]
+    fn learns_a_pattern() {
+        let text = "abc".repeat(10);
+        let model = CharLm::new(&text, 4, 8, 0);
+        assert_eq!(model.vocab(), ['a', 'b', 'c']);
+
+        let losses = model.train(&text, 40, 10, 0.1);
+        assert!(losses[39] < losses[0]);
+        assert_eq!(model.generate("ab", 4), "abcabc");
+    }
+}
@@ -0,0 +1,359 @@
+//! Helpers turning raw data into `(inputs, target)` samples, and a [`DatasetCache`] keeping the
+//! results of expensive preprocessing on disk.
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Slide a window of `window` values over `series`, pairing every window with the value
+/// `horizon` steps after its last value.
+///
+/// With `series = [1, 2, 3, 4, 5]`, `window = 2` and `horizon = 1` the samples are
+/// `([1, 2], 3)`, `([2, 3], 4)` and `([3, 4], 5)`.
+pub fn windowed(series: &[f64], window: usize, horizon: usize) -> Vec<(Vec<f64>, f64)> {
+    assert!(window > 0, "the window must hold at least one value");
+    assert!(horizon > 0, "the target must come after the window");
+
+    let span = window + horizon - 1;
+    if series.len() <= span {
+        return vec![];
+    }
+
+    (0..series.len() - span)
+        .map(|start| {
+            let inputs = series[start..start + window].to_vec();
+            (inputs, series[start + span])
+        })
+        .collect()
+}
+
+/// Like [`windowed`] but every sample is standardized with the mean and standard deviation of
+/// its window, the target included, so the model learns the shape of the series rather than
+/// its level. Windows with no variance are only shifted.
+pub fn windowed_normalized(series: &[f64], window: usize, horizon: usize) -> Vec<(Vec<f64>, f64)> {
+    windowed(series, window, horizon)
+        .into_iter()
+        .map(|(inputs, target)| {
+            let n = inputs.len() as f64;
+            let mean = inputs.iter().sum::<f64>() / n;
+            let std_dev = (inputs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt();
+            let scale = if std_dev > 0.0 { std_dev } else { 1.0 };
+
+            (
+                inputs.iter().map(|x| (x - mean) / scale).collect(),
+                (target - mean) / scale,
+            )
+        })
+        .collect()
+}
+
+/// Word pairs for training embeddings the word2vec way, see [`skip_gram`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkipGram {
+    /// The distinct words of the corpus, in order of first appearance.
+    pub vocab: Vec<String>,
+    /// `(center, context, label)` with indices into the vocabulary: label 1 for words seen
+    /// together, 0 for negative samples.
+    pub samples: Vec<(usize, usize, f64)>,
+}
+
+/// Pair every word of the whitespace separated `corpus` with the words at most `window` words
+/// away, and each of those pairs with `negatives` random words, drawn like word2vec does
+/// proportionally to their frequency to the power 3/4. The same seed always gives the same
+/// samples.
+pub fn skip_gram(corpus: &str, window: usize, negatives: usize, seed: u64) -> SkipGram {
+    let mut vocab: Vec<String> = vec![];
+    let mut counts: Vec<usize> = vec![];
+    let words: Vec<usize> = corpus
+        .split_whitespace()
+        .map(|word| match vocab.iter().position(|w| w == word) {
+            Some(i) => {
+                counts[i] += 1;
+                i
+            }
+            None => {
+                vocab.push(word.to_string());
+                counts.push(1);
+                vocab.len() - 1
+            }
+        })
+        .collect();
+
+    let cumulative: Vec<f64> = counts
+        .iter()
+        .scan(0.0, |total, c| {
+            *total += (*c as f64).powf(0.75);
+            Some(*total)
+        })
+        .collect();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut negative = |context: usize| loop {
+        let target = rng.gen_range(0.0..cumulative[cumulative.len() - 1]);
+        let word = cumulative
+            .partition_point(|c| *c <= target)
+            .min(vocab.len() - 1);
+        // A single word vocabulary has nothing else to offer.
+        if word != context || vocab.len() == 1 {
+            return word;
+        }
+    };
+
+    let mut samples = vec![];
+    for (i, &center) in words.iter().enumerate() {
+        let end = (i + window + 1).min(words.len());
+        for (j, &context) in words
+            .iter()
+            .enumerate()
+            .take(end)
+            .skip(i.saturating_sub(window))
+        {
+            if j == i {
+                continue;
+            }
+            samples.push((center, context, 1.0));
+            for _ in 0..negatives {
+                samples.push((center, negative(context), 0.0));
+            }
+        }
+    }
+
+    SkipGram { vocab, samples }
+}
+
+/// A sample that can be stored in a [`DatasetCache`], as one line of text.
+pub trait CachedSample: Sized {
+    fn to_line(&self) -> String;
+    fn from_line(line: &str) -> Option<Self>;
+}
+
+fn parse_numbers(line: &str) -> Option<Vec<f64>> {
+    line.split_whitespace().map(|x| x.parse().ok()).collect()
+}
+
+// The Debug form of a float parses back to the same value.
+fn format_numbers(xs: &[f64]) -> String {
+    xs.iter()
+        .map(|x| format!("{x:?}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl CachedSample for Vec<f64> {
+    fn to_line(&self) -> String {
+        format_numbers(self)
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        parse_numbers(line)
+    }
+}
+
+/// The inputs followed by the target.
+impl CachedSample for (Vec<f64>, f64) {
+    fn to_line(&self) -> String {
+        format!("{} {:?}", format_numbers(&self.0), self.1)
+            .trim_start()
+            .to_string()
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut numbers = parse_numbers(line)?;
+        let target = numbers.pop()?;
+        Some((numbers, target))
+    }
+}
+
+/// The inputs followed by the class.
+impl CachedSample for (Vec<f64>, usize) {
+    fn to_line(&self) -> String {
+        format!("{} {}", format_numbers(&self.0), self.1)
+            .trim_start()
+            .to_string()
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let (inputs, class) = line.rsplit_once(' ').unwrap_or(("", line));
+        Some((parse_numbers(inputs)?, class.parse().ok()?))
+    }
+}
+
+/// Preprocessed datasets stored in a directory, keyed by a hash of their source data and of a
+/// description of the preprocessing, so the runs of a sweep only decode or tokenize once.
+///
+/// ```no_run
+/// use neuron::datasets::{windowed, DatasetCache};
+///
+/// let source = std::fs::read_to_string("series.txt").unwrap();
+/// let cache = DatasetCache::new("cache");
+/// let samples: Vec<(Vec<f64>, f64)> = cache
+///     .load_or_compute(source.as_bytes(), "windowed 16 1", || {
+///         let series: Vec<f64> = source.lines().map(|l| l.parse().unwrap()).collect();
+///         windowed(&series, 16, 1)
+///     })
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct DatasetCache {
+    dir: PathBuf,
+}
+
+impl DatasetCache {
+    /// The first line of every cache file, changed with the format.
+    const HEADER: &'static str = "neuron-dataset-cache 1";
+
+    /// A cache in `dir`, which is created when the first dataset is stored.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The file the samples preprocessed from `source` by `transforms` are stored in.
+    /// `transforms` has to change whenever the preprocessing does, e.g. with its parameters.
+    pub fn path(&self, source: &[u8], transforms: &str) -> PathBuf {
+        // FNV-1a, stable across runs and Rust versions unlike the std hashers.
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in source.iter().chain(&[0]).chain(transforms.as_bytes()) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        self.dir.join(format!("{hash:016x}.samples"))
+    }
+
+    /// The samples stored for `source` and `transforms`, or else the ones returned by
+    /// `preprocess`, which are stored for the next time. Missing or unreadable cache files are
+    /// recomputed.
+    pub fn load_or_compute<T: CachedSample>(
+        &self,
+        source: &[u8],
+        transforms: &str,
+        preprocess: impl FnOnce() -> Vec<T>,
+    ) -> io::Result<Vec<T>> {
+        let path = self.path(source, transforms);
+        if let Some(samples) = fs::read_to_string(&path).ok().and_then(|t| Self::load(&t)) {
+            return Ok(samples);
+        }
+
+        let samples = preprocess();
+        let mut out = format!("{}\n", Self::HEADER);
+        for sample in &samples {
+            out.push_str(&sample.to_line());
+            out.push('\n');
+        }
+
+        // Written aside then renamed, so concurrent runs never read a partial file.
+        fs::create_dir_all(&self.dir)?;
+        let partial = path.with_extension(format!("partial-{}", std::process::id()));
+        fs::write(&partial, out)?;
+        fs::rename(&partial, &path)?;
+        Ok(samples)
+    }
+
+    fn load<T: CachedSample>(text: &str) -> Option<Vec<T>> {
+        let mut lines = text.lines();
+        if lines.next() != Some(Self::HEADER) {
+            return None;
+        }
+        lines.map(T::from_line).collect()
+    }
+
+    /// Remove every stored dataset.
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::{skip_gram, windowed, windowed_normalized, DatasetCache};
+
+    #[test]
+    fn windows() {
+        let series = [1.0, 2.0, 3.0, 4.0, 5.0];
+
+        assert_eq!(
+            windowed(&series, 2, 1),
+            vec![
+                (vec![1.0, 2.0], 3.0),
+                (vec![2.0, 3.0], 4.0),
+                (vec![3.0, 4.0], 5.0)
+            ]
+        );
+        assert_eq!(windowed(&series, 3, 2), vec![(vec![1.0, 2.0, 3.0], 5.0)]);
+        assert!(windowed(&series, 4, 2).is_empty());
+    }
+
+    #[test]
+    fn normalized_windows() {
+        let samples = windowed_normalized(&[10.0, 12.0, 14.0, 3.0, 3.0, 3.0], 2, 1);
+
+        assert_eq!(samples[0], (vec![-1.0, 1.0], 3.0));
+        assert_eq!(samples[3], (vec![0.0, 0.0], 0.0));
+    }
+
+    #[test]
+    fn skip_gram_pairs() {
+        let data = skip_gram("the cat sat on the mat", 1, 2, 0);
+
+        assert_eq!(data.vocab, vec!["the", "cat", "sat", "on", "mat"]);
+        let positives: Vec<(usize, usize)> = data
+            .samples
+            .iter()
+            .filter(|s| s.2 == 1.0)
+            .map(|s| (s.0, s.1))
+            .collect();
+        assert_eq!(positives[..3], [(0, 1), (1, 0), (1, 2)]);
+        assert_eq!(positives.len(), 10);
+        assert_eq!(data.samples.len(), 30);
+        // Every positive pair is followed by its negatives, none of them the true context.
+        for pair in data.samples.chunks(3) {
+            assert_eq!((pair[1].2, pair[2].2), (0.0, 0.0));
+            assert!(pair[1].1 != pair[0].1 && pair[2].1 != pair[0].1);
+        }
+        assert_eq!(data, skip_gram("the cat sat on the mat", 1, 2, 0));
+    }
+
+    #[test]
+    fn cache() {
+        let dir = std::env::temp_dir().join(format!("neuron-cache-{}", std::process::id()));
+        let cache = DatasetCache::new(&dir);
+        let source = b"1 2 3 4 5";
+        let calls = Cell::new(0);
+        let preprocess = || {
+            calls.set(calls.get() + 1);
+            windowed(&[1.0, 2.0, 3.0, 4.0, 5.0], 2, 1)
+        };
+
+        let first = cache
+            .load_or_compute(source, "windowed 2 1", preprocess)
+            .unwrap();
+        let second = cache
+            .load_or_compute(source, "windowed 2 1", preprocess)
+            .unwrap();
+        assert_eq!(first, second);
+        assert_eq!(calls.get(), 1);
+
+        assert_ne!(
+            cache.path(source, "windowed 3 1"),
+            cache.path(source, "windowed 2 1")
+        );
+        let classes: Vec<(Vec<f64>, usize)> = cache
+            .load_or_compute(source, "classes", || {
+                vec![(vec![0.1, -2.5], 3), (vec![], 1)]
+            })
+            .unwrap();
+        let cached: Vec<(Vec<f64>, usize)> =
+            cache.load_or_compute(source, "classes", Vec::new).unwrap();
+        assert_eq!(cached, classes);
+
+        cache.clear().unwrap();
+        assert!(!dir.exists());
+        cache.clear().unwrap();
+    }
+}
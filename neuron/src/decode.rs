@@ -0,0 +1,161 @@
+//! Generating sequences from a trained model, one token at a time.
+//!
+//! Every helper drives a step function that gets the last token and the state of the model,
+//! e.g. the hidden state of a recurrent network, and returns the logits of the next token and
+//! the next state. The returned tokens don't include `start`.
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::ops::{argmax, log_softmax_f64};
+
+/// Always pick the most likely next token, ties go to the lowest one.
+pub fn greedy<S>(
+    mut step: impl FnMut(usize, &S) -> (Vec<f64>, S),
+    mut state: S,
+    start: usize,
+    len: usize,
+) -> Vec<usize> {
+    let mut tokens = vec![];
+    let mut token = start;
+    for _ in 0..len {
+        let (logits, next) = checked_step(&mut step, token, &state);
+        token = argmax(&logits);
+        tokens.push(token);
+        state = next;
+    }
+    tokens
+}
+
+/// Sample every next token from the softmax of its logits divided by `temperature`, among the
+/// `k` most likely ones only. Lower temperatures are closer to [`greedy`], higher ones more
+/// varied.
+pub fn sample_top_k<S>(
+    mut step: impl FnMut(usize, &S) -> (Vec<f64>, S),
+    mut state: S,
+    start: usize,
+    len: usize,
+    k: usize,
+    temperature: f64,
+    seed: u64,
+) -> Vec<usize> {
+    assert!(k > 0, "k must be at least 1");
+    assert!(temperature > 0.0, "the temperature must be positive");
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut tokens = vec![];
+    let mut token = start;
+    for _ in 0..len {
+        let (logits, next) = checked_step(&mut step, token, &state);
+        let mut candidates: Vec<usize> = (0..logits.len()).collect();
+        candidates.sort_by(|a, b| logits[*b].total_cmp(&logits[*a]));
+        candidates.truncate(k);
+
+        let scaled: Vec<f64> = candidates
+            .iter()
+            .map(|c| logits[*c] / temperature)
+            .collect();
+        let probs: Vec<f64> = log_softmax_f64(&scaled).iter().map(|l| l.exp()).collect();
+
+        let mut target = rng.gen::<f64>();
+        token = candidates[candidates.len() - 1];
+        for (c, p) in candidates.iter().zip(&probs) {
+            if target < *p {
+                token = *c;
+                break;
+            }
+            target -= p;
+        }
+
+        tokens.push(token);
+        state = next;
+    }
+    tokens
+}
+
+/// Keep the `beam_width` most likely sequences at every step and return the most likely one
+/// after `len` steps. Unlike [`greedy`], a token that is less likely now can win if it makes
+/// the following ones much more likely.
+pub fn beam_search<S: Clone>(
+    mut step: impl FnMut(usize, &S) -> (Vec<f64>, S),
+    state: S,
+    start: usize,
+    len: usize,
+    beam_width: usize,
+) -> Vec<usize> {
+    assert!(beam_width > 0, "beams need a width of at least 1");
+
+    // Every beam is its tokens, their total log probability and the state after the last one.
+    let mut beams = vec![(vec![], 0.0, state)];
+    for _ in 0..len {
+        let mut candidates = vec![];
+        for (tokens, score, state) in &beams {
+            let last = tokens.last().copied().unwrap_or(start);
+            let (logits, next) = checked_step(&mut step, last, state);
+            for (token, log_prob) in log_softmax_f64(&logits).into_iter().enumerate() {
+                let mut tokens: Vec<usize> = tokens.clone();
+                tokens.push(token);
+                candidates.push((tokens, score + log_prob, next.clone()));
+            }
+        }
+
+        // The sort is stable, equally likely sequences keep the order of their beams.
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        candidates.truncate(beam_width);
+        beams = candidates;
+    }
+
+    beams
+        .into_iter()
+        .next()
+        .map_or(vec![], |(tokens, _, _)| tokens)
+}
+
+/// Call `step`, which has to return at least one logit.
+fn checked_step<S>(
+    step: &mut impl FnMut(usize, &S) -> (Vec<f64>, S),
+    token: usize,
+    state: &S,
+) -> (Vec<f64>, S) {
+    let (logits, next) = step(token, state);
+    assert!(!logits.is_empty(), "the step function returned no logits");
+    (logits, next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{beam_search, greedy, sample_top_k};
+
+    /// A Markov chain over three tokens: the logits of the next token only depend on the last
+    /// one.
+    fn step(token: usize, _: &()) -> (Vec<f64>, ()) {
+        let probs: [[f64; 3]; 3] = [[0.0, 0.6, 0.4], [0.34, 0.33, 0.33], [0.9, 0.05, 0.05]];
+        (probs[token].iter().map(|p| p.ln()).collect(), ())
+    }
+
+    #[test]
+    fn decoding() {
+        assert_eq!(greedy(step, (), 0, 2), vec![1, 0]);
+        // 0.4 * 0.9 beats 0.6 * 0.34.
+        assert_eq!(beam_search(step, (), 0, 2, 2), vec![2, 0]);
+        assert_eq!(beam_search(step, (), 0, 2, 1), greedy(step, (), 0, 2));
+    }
+
+    #[test]
+    fn top_k_sampling() {
+        let tokens = sample_top_k(step, (), 0, 20, 1, 1.0, 3);
+        assert_eq!(tokens, greedy(step, (), 0, 20));
+
+        let tokens = sample_top_k(step, (), 0, 200, 2, 1.0, 3);
+        assert_eq!(tokens, sample_top_k(step, (), 0, 200, 2, 1.0, 3));
+        // 0 can't follow itself, and 2 isn't among the 2 most likely tokens after itself.
+        for pair in tokens.windows(2) {
+            assert!(pair != [0, 0] && pair != [2, 2]);
+        }
+        assert!(tokens.contains(&2));
+    }
+
+    #[test]
+    #[should_panic(expected = "the step function returned no logits")]
+    fn no_logits() {
+        sample_top_k(|_, _: &()| (vec![], ()), (), 0, 1, 2, 1.0, 3);
+    }
+}
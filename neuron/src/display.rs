@@ -0,0 +1,112 @@
+//! Per-thread settings for showing graphs: symbols shown instead of op names, e.g. `σ` for
+//! `sigmoid`, and the number of decimals of data and gradients. They apply to the `Display` of
+//! [`Val`], which the notebook `visualize` uses for its nodes, to [`Val::tree`] and to the
+//! titles of [`Val::to_svg`].
+//!
+//! ```
+//! use neuron::{display, val::Val};
+//!
+//! display::register_symbol("sigmoid", "σ");
+//! display::set_precision(Some(2));
+//! let y = Val::new(0.5, "x").sigmoid();
+//! assert_eq!(y.to_string(), "| op:σ, v:0.62, g:0.00");
+//! display::reset();
+//! ```
+//!
+//! [`Val`]: crate::val::Val
+//! [`Val::tree`]: crate::val::Val::tree
+//! [`Val::to_svg`]: crate::val::Val::to_svg
+use std::{cell::RefCell, collections::BTreeMap};
+
+thread_local! {
+    static SETTINGS: RefCell<Settings> = const {
+        RefCell::new(Settings {
+            symbols: BTreeMap::new(),
+            precision: None,
+        })
+    };
+}
+
+struct Settings {
+    symbols: BTreeMap<String, String>,
+    precision: Option<usize>,
+}
+
+/// Show `symbol` instead of the name of `op`, e.g. for the ops of a module.
+pub fn register_symbol(op: &str, symbol: &str) {
+    SETTINGS.with(|s| {
+        s.borrow_mut()
+            .symbols
+            .insert(op.to_string(), symbol.to_string())
+    });
+}
+
+/// The usual mathematical symbols for the ops that have one.
+pub fn use_math_symbols() {
+    for (op, symbol) in [
+        ("sigmoid", "σ"),
+        ("sqrt", "√"),
+        ("abs", "|·|"),
+        ("recip", "1/·"),
+    ] {
+        register_symbol(op, symbol);
+    }
+}
+
+/// Round data and gradients to `precision` decimals, `None` for the default of every output.
+pub fn set_precision(precision: Option<usize>) {
+    SETTINGS.with(|s| s.borrow_mut().precision = precision);
+}
+
+/// Go back to the op names and default precisions.
+pub fn reset() {
+    SETTINGS.with(|s| {
+        let mut settings = s.borrow_mut();
+        settings.symbols.clear();
+        settings.precision = None;
+    });
+}
+
+/// The symbol registered for `op`, or `op` itself.
+pub(crate) fn op(op: &str) -> String {
+    SETTINGS.with(|s| {
+        s.borrow()
+            .symbols
+            .get(op)
+            .cloned()
+            .unwrap_or_else(|| op.to_string())
+    })
+}
+
+/// `x` with the decimals set by [`set_precision`], or else `default` decimals, or else as
+/// many as needed.
+pub(crate) fn number(x: f64, default: Option<usize>) -> String {
+    match SETTINGS.with(|s| s.borrow().precision).or(default) {
+        Some(precision) => format!("{x:.precision$}"),
+        None => x.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::val::Val;
+
+    #[test]
+    fn symbols_and_precision() {
+        let x = Val::new(4.0, "x");
+        let y = (x.sqrt() + Val::from(1.0 / 3.0)).with_label("y");
+        assert_eq!(y.to_string(), "y| op:+, v:2.3333333333333335, g:0");
+
+        super::use_math_symbols();
+        super::register_symbol("+", "plus");
+        super::set_precision(Some(1));
+        assert_eq!(y.to_string(), "y| op:plus, v:2.3, g:0.0");
+        assert_eq!(y.tree(1).lines().nth(1), Some("  √ (data: 2.0, grad: 0.0)"));
+
+        super::reset();
+        assert_eq!(
+            y.tree(1).lines().nth(1),
+            Some("  sqrt (data: 2.0000, grad: 0.0000)")
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Averaging the outputs of several models.
+use std::{io, path::Path};
+
+use crate::{mlp::Mlp, ops::argmax};
+
+/// Models with the same inputs and outputs whose outputs are averaged, usually more accurate
+/// than any one of them.
+pub struct Ensemble {
+    models: Vec<Mlp>,
+}
+
+impl Ensemble {
+    pub fn new(models: Vec<Mlp>) -> Self {
+        assert!(!models.is_empty(), "an ensemble needs at least one model");
+        Self { models }
+    }
+
+    /// Load the models saved by a [`Snapshots`] callback, or any [`Mlp::save`].
+    ///
+    /// [`Snapshots`]: crate::callback::Snapshots
+    pub fn from_snapshots(paths: &[impl AsRef<Path>]) -> io::Result<Self> {
+        let models = paths.iter().map(Mlp::load).collect::<io::Result<_>>()?;
+        Ok(Self::new(models))
+    }
+
+    pub fn models(&self) -> &[Mlp] {
+        &self.models
+    }
+
+    /// The mean of the outputs of the models for `xs`.
+    pub fn forward(&self, xs: &[f64]) -> Vec<f64> {
+        let mut total: Vec<f64> = vec![];
+        for model in &self.models {
+            let outputs = model.forward(xs);
+            total.resize(outputs.len(), 0.0);
+            for (t, o) in total.iter_mut().zip(&outputs) {
+                *t += o.data();
+            }
+        }
+        total.iter().map(|t| t / self.models.len() as f64).collect()
+    }
+
+    /// The index of the highest mean output for `xs`, ties go to the lowest index.
+    pub fn predict_class(&self, xs: &[f64]) -> usize {
+        argmax(&self.forward(xs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ensemble;
+    use crate::{
+        callback::Snapshots, estimator::MlpClassifier, mlp::Mlp, neuron::Activation,
+        schedule::CyclicCosine,
+    };
+
+    #[test]
+    fn mean_outputs() {
+        let a = Mlp::new(1, vec![1]).with_output_activation(Activation::Linear);
+        let b = Mlp::new(1, vec![1]).with_output_activation(Activation::Linear);
+        for (model, bias) in [(&a, 1.0), (&b, 3.0)] {
+            let params = model.parameters();
+            params[0].set_data(0.0);
+            params[1].set_data(bias);
+        }
+
+        let ensemble = Ensemble::new(vec![a, b]);
+        assert_eq!(ensemble.forward(&[5.0]), vec![2.0]);
+    }
+
+    #[test]
+    fn snapshot_ensemble() {
+        let x = vec![
+            vec![-1.0, -1.0],
+            vec![-0.8, -1.2],
+            vec![1.0, 1.0],
+            vec![0.9, 1.2],
+        ];
+        let y = vec![0, 0, 1, 1];
+
+        let dir = std::env::temp_dir().join(format!("neuron-snapshots-{}", std::process::id()));
+        let snapshots = Snapshots::new(&dir, 50);
+        let mut classifier = MlpClassifier::new(vec![8])
+            .with_epochs(150)
+            .with_schedule(CyclicCosine::new(0.1, 0.0, 50, 3))
+            .with_callback(snapshots.clone());
+        classifier.fit(&x, &y);
+
+        let paths = snapshots.paths();
+        assert_eq!(paths.len(), 3);
+        let ensemble = Ensemble::from_snapshots(&paths).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let predictions: Vec<usize> = x.iter().map(|xs| ensemble.predict_class(xs)).collect();
+        assert_eq!(predictions, y);
+    }
+}
@@ -0,0 +1,443 @@
+//! Scikit-learn style estimators over plain `f64` data, hiding the graph and the optimizer.
+//!
+//! Training is full batch gradient descent: every epoch builds the loss over all the samples,
+//! back propagates once and takes a single [`Sgd`] step.
+use std::rc::Rc;
+
+use crate::{
+    attack,
+    callback::Callback,
+    loss::{self, Reduction},
+    mlp::Mlp,
+    neuron::Activation,
+    ops::log_softmax_f64,
+    optim::Sgd,
+    schedule::Schedule,
+    val::Val,
+};
+
+/// Fit `model` by minimizing the loss returned by `loss` for `epochs` steps, with the learning
+/// rates of `schedule` if any, calling every callback before training, with the gradient norms
+/// of every step and after every step.
+fn train(
+    model: &Mlp,
+    learning_rate: f64,
+    epochs: usize,
+    schedule: Option<Rc<dyn Schedule>>,
+    callbacks: &mut [Box<dyn Callback>],
+    loss: impl Fn(&Mlp) -> Val,
+) {
+    let mut sgd = Sgd::new(model.parameters(), learning_rate);
+    if let Some(schedule) = schedule {
+        sgd = sgd.with_schedule(Box::new(schedule));
+    }
+
+    for callback in callbacks.iter_mut() {
+        callback.on_train_begin(model);
+    }
+
+    for _ in 0..epochs {
+        let l = loss(model);
+        sgd.zero_grad();
+        l.back_prop_gradient();
+
+        let norms = model.gradient_norms();
+        for callback in callbacks.iter_mut() {
+            callback.on_gradients(model, &norms);
+        }
+        sgd.step();
+        l.free_graph();
+
+        for callback in callbacks.iter_mut() {
+            callback.on_step(model, sgd.steps());
+        }
+    }
+}
+
+/// Regression of a single target trained on the mean squared error.
+pub struct MlpRegressor {
+    hidden_layers: Vec<usize>,
+    learning_rate: f64,
+    epochs: usize,
+    schedule: Option<Rc<dyn Schedule>>,
+    callbacks: Vec<Box<dyn Callback>>,
+    model: Option<Mlp>,
+}
+
+impl MlpRegressor {
+    pub fn new(hidden_layers: Vec<usize>) -> Self {
+        Self {
+            hidden_layers,
+            learning_rate: 0.01,
+            epochs: 100,
+            schedule: None,
+            callbacks: vec![],
+            model: None,
+        }
+    }
+
+    pub fn with_learning_rate(mut self, learning_rate: f64) -> Self {
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    pub fn with_epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    /// Take the learning rate of every epoch from `schedule` instead of the fixed one.
+    pub fn with_schedule(mut self, schedule: impl Schedule + 'static) -> Self {
+        self.schedule = Some(Rc::new(schedule));
+        self
+    }
+
+    /// Call `callback` after every epoch of training.
+    pub fn with_callback(mut self, callback: impl Callback + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Train a new model on the samples `x` and their targets `y`.
+    pub fn fit(&mut self, x: &[Vec<f64>], y: &[f64]) {
+        self.fit_weighted(x, y, &vec![1.0; x.len()]);
+    }
+
+    /// Like [`MlpRegressor::fit`] with the loss of every sample scaled by its weight.
+    pub fn fit_weighted(&mut self, x: &[Vec<f64>], y: &[f64], weights: &[f64]) {
+        assert!(!x.is_empty(), "cannot fit on an empty dataset");
+        assert_eq!(x.len(), y.len(), "every sample needs a target");
+
+        let mut layers = self.hidden_layers.clone();
+        layers.push(1);
+        let model = Mlp::new(x[0].len(), layers).with_output_activation(Activation::Linear);
+
+        let targets: Vec<Vec<f64>> = y.iter().map(|y| vec![*y]).collect();
+        train(
+            &model,
+            self.learning_rate,
+            self.epochs,
+            self.schedule.clone(),
+            &mut self.callbacks,
+            |model| {
+                let predictions: Vec<Vec<Val>> = x.iter().map(|xs| model.forward(xs)).collect();
+                let losses = loss::mse(&predictions, &targets, Reduction::None);
+                loss::weighted(losses, weights, Reduction::Mean).remove(0)
+            },
+        );
+
+        self.model = Some(model);
+    }
+
+    pub fn predict(&self, x: &[Vec<f64>]) -> Vec<f64> {
+        let model = self
+            .model
+            .as_ref()
+            .expect("fit must be called before predict");
+        x.iter().map(|xs| model.forward(xs)[0].data()).collect()
+    }
+
+    /// The coefficient of determination (R²) of the predictions for `x`.
+    pub fn score(&self, x: &[Vec<f64>], y: &[f64]) -> f64 {
+        let predictions = self.predict(x);
+        let mean = y.iter().sum::<f64>() / y.len() as f64;
+
+        let residual: f64 = predictions
+            .iter()
+            .zip(y)
+            .map(|(p, y)| (y - p).powi(2))
+            .sum();
+        let total: f64 = y.iter().map(|y| (y - mean).powi(2)).sum();
+
+        1.0 - residual / total
+    }
+}
+
+/// Multi-class classification trained on the multi-class hinge loss, the classes are `0..n`.
+pub struct MlpClassifier {
+    hidden_layers: Vec<usize>,
+    learning_rate: f64,
+    epochs: usize,
+    schedule: Option<Rc<dyn Schedule>>,
+    callbacks: Vec<Box<dyn Callback>>,
+    adversarial_epsilon: Option<f64>,
+    model: Option<Mlp>,
+}
+
+impl MlpClassifier {
+    pub fn new(hidden_layers: Vec<usize>) -> Self {
+        Self {
+            hidden_layers,
+            learning_rate: 0.05,
+            epochs: 100,
+            schedule: None,
+            callbacks: vec![],
+            adversarial_epsilon: None,
+            model: None,
+        }
+    }
+
+    pub fn with_learning_rate(mut self, learning_rate: f64) -> Self {
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    pub fn with_epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    /// Take the learning rate of every epoch from `schedule` instead of the fixed one.
+    pub fn with_schedule(mut self, schedule: impl Schedule + 'static) -> Self {
+        self.schedule = Some(Rc::new(schedule));
+        self
+    }
+
+    /// Call `callback` after every epoch of training.
+    pub fn with_callback(mut self, callback: impl Callback + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Train on [`attack::fgsm`] perturbations of every sample, recomputed each epoch against
+    /// the current model, next to the samples themselves. This makes the model more robust to
+    /// perturbations of up to `epsilon` per feature.
+    pub fn with_adversarial_training(mut self, epsilon: f64) -> Self {
+        self.adversarial_epsilon = Some(epsilon);
+        self
+    }
+
+    /// Train a new model on the samples `x` and their classes `y`.
+    pub fn fit(&mut self, x: &[Vec<f64>], y: &[usize]) {
+        self.fit_weighted(x, y, &vec![1.0; x.len()]);
+    }
+
+    /// Like [`MlpClassifier::fit`] with the loss of every sample scaled by its weight.
+    pub fn fit_weighted(&mut self, x: &[Vec<f64>], y: &[usize], weights: &[f64]) {
+        assert!(!x.is_empty(), "cannot fit on an empty dataset");
+        assert_eq!(x.len(), y.len(), "every sample needs a class");
+
+        let num_classes = y.iter().max().unwrap() + 1;
+        let mut layers = self.hidden_layers.clone();
+        layers.push(num_classes);
+        let model = Mlp::new(x[0].len(), layers).with_output_activation(Activation::Linear);
+
+        let adversarial_epsilon = self.adversarial_epsilon;
+        train(
+            &model,
+            self.learning_rate,
+            self.epochs,
+            self.schedule.clone(),
+            &mut self.callbacks,
+            |model| {
+                let mut scores: Vec<Vec<Val>> = x.iter().map(|xs| model.forward(xs)).collect();
+                let mut classes = y.to_vec();
+                let mut weights = weights.to_vec();
+                if let Some(epsilon) = adversarial_epsilon {
+                    for (xs, class) in x.iter().zip(y) {
+                        scores.push(model.forward(&attack::fgsm(model, xs, *class, epsilon)));
+                    }
+                    classes.extend_from_slice(y);
+                    weights.extend_from_within(..);
+                }
+
+                let losses = loss::hinge(&scores, &classes, Reduction::None);
+                loss::weighted(losses, &weights, Reduction::Mean).remove(0)
+            },
+        );
+
+        self.model = Some(model);
+    }
+
+    /// Train a new model on the samples `x` and their classes `y` while also matching the
+    /// outputs of a trained `teacher`, typically a larger model, whose weights are left as they
+    /// are.
+    ///
+    /// The loss is `alpha` times the cross-entropy with the classes plus `1 - alpha` times the
+    /// KL divergence between the softmax of the teacher and student outputs, both divided by
+    /// `temperature`. Temperatures above 1 soften the teacher's distribution so the student
+    /// also learns how it ranks the wrong classes. The KL term is scaled by `temperature²` to
+    /// keep its gradients comparable to the cross-entropy's.
+    pub fn fit_distilled(
+        &mut self,
+        x: &[Vec<f64>],
+        y: &[usize],
+        teacher: &Mlp,
+        temperature: f64,
+        alpha: f64,
+    ) {
+        assert!(!x.is_empty(), "cannot fit on an empty dataset");
+        assert_eq!(x.len(), y.len(), "every sample needs a class");
+        assert!(temperature > 0.0, "the temperature must be positive");
+        assert!(
+            (0.0..=1.0).contains(&alpha),
+            "alpha must be between 0 and 1"
+        );
+
+        let num_classes = teacher.layers().last().map_or(0, |l| l.num_outputs());
+        assert!(
+            y.iter().all(|c| *c < num_classes),
+            "the teacher must have an output per class"
+        );
+        let mut layers = self.hidden_layers.clone();
+        layers.push(num_classes);
+        let model = Mlp::new(x[0].len(), layers).with_output_activation(Activation::Linear);
+
+        // The teacher is frozen, its soft targets are plain numbers.
+        let soft_targets: Vec<Vec<f64>> = x
+            .iter()
+            .map(|xs| {
+                let outputs = teacher.forward(xs);
+                let logits: Vec<f64> = outputs.iter().map(|o| o.data() / temperature).collect();
+                log_softmax_f64(&logits).iter().map(|l| l.exp()).collect()
+            })
+            .collect();
+
+        train(
+            &model,
+            self.learning_rate,
+            self.epochs,
+            self.schedule.clone(),
+            &mut self.callbacks,
+            |model| {
+                let logits: Vec<Vec<Val>> = x.iter().map(|xs| model.forward(xs)).collect();
+                let softened: Vec<Vec<Val>> = logits
+                    .iter()
+                    .map(|l| l.iter().map(|z| z / temperature).collect())
+                    .collect();
+
+                let hard = loss::cross_entropy(&logits, y, Reduction::Mean).remove(0);
+                let soft = loss::kl_div(&softened, &soft_targets, Reduction::Mean).remove(0);
+                alpha * hard + (1.0 - alpha) * temperature.powi(2) * soft
+            },
+        );
+
+        self.model = Some(model);
+    }
+
+    /// The trained model, e.g. to serve as the teacher of [`MlpClassifier::fit_distilled`].
+    pub fn model(&self) -> Option<&Mlp> {
+        self.model.as_ref()
+    }
+
+    pub fn predict(&self, x: &[Vec<f64>]) -> Vec<usize> {
+        let model = self
+            .model
+            .as_ref()
+            .expect("fit must be called before predict");
+
+        x.iter().map(|xs| model.predict_class(xs)).collect()
+    }
+
+    /// The `k` most likely classes of a single sample, see [`Mlp::top_k`].
+    pub fn top_k(&self, xs: &[f64], k: usize) -> Vec<usize> {
+        let model = self
+            .model
+            .as_ref()
+            .expect("fit must be called before predict");
+        model.top_k(xs, k)
+    }
+
+    /// The fraction of samples in `x` whose class is predicted correctly.
+    pub fn score(&self, x: &[Vec<f64>], y: &[usize]) -> f64 {
+        let correct = self
+            .predict(x)
+            .iter()
+            .zip(y)
+            .filter(|(p, y)| p == y)
+            .count();
+        correct as f64 / y.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MlpClassifier, MlpRegressor};
+
+    #[test]
+    fn regressor() {
+        let x: Vec<Vec<f64>> = (0..10).map(|i| vec![i as f64 / 10.0]).collect();
+        let y: Vec<f64> = x.iter().map(|x| 2.0 * x[0] - 1.0).collect();
+
+        let mut regressor = MlpRegressor::new(vec![8])
+            .with_learning_rate(0.1)
+            .with_epochs(500);
+        regressor.fit(&x, &y);
+
+        assert!(regressor.score(&x, &y) > 0.9);
+    }
+
+    #[test]
+    fn classifier() {
+        let x = vec![
+            vec![-1.0, -1.0],
+            vec![-0.8, -1.2],
+            vec![-1.2, -0.7],
+            vec![1.0, 1.0],
+            vec![0.9, 1.2],
+            vec![1.1, 0.8],
+        ];
+        let y = vec![0, 0, 0, 1, 1, 1];
+
+        let mut classifier = MlpClassifier::new(vec![8]).with_epochs(200);
+        classifier.fit(&x, &y);
+
+        assert_eq!(classifier.score(&x, &y), 1.0);
+        assert_eq!(classifier.predict(&[vec![-0.9, -0.9]]), vec![0]);
+    }
+
+    #[test]
+    fn weighted_classifier() {
+        // The same point carries both classes, the weights decide which one wins.
+        let x = vec![vec![1.0, 1.0], vec![1.0, 1.0]];
+        let y = vec![0, 1];
+
+        let mut classifier = MlpClassifier::new(vec![4]).with_epochs(100);
+        classifier.fit_weighted(&x, &y, &[1.0, 9.0]);
+
+        assert_eq!(classifier.predict(&x), vec![1, 1]);
+    }
+
+    #[test]
+    fn distilled_classifier() {
+        let x = vec![
+            vec![-1.0, -1.0],
+            vec![-0.8, -1.2],
+            vec![1.0, 1.0],
+            vec![0.9, 1.2],
+            vec![1.0, -1.0],
+            vec![1.2, -0.9],
+        ];
+        let y = vec![0, 0, 1, 1, 2, 2];
+
+        let mut teacher = MlpClassifier::new(vec![16]).with_epochs(200);
+        teacher.fit(&x, &y);
+
+        let mut student = MlpClassifier::new(vec![8])
+            .with_learning_rate(0.1)
+            .with_epochs(500);
+        student.fit_distilled(&x, &y, teacher.model().unwrap(), 2.0, 0.5);
+
+        assert_eq!(student.score(&x, &y), 1.0);
+    }
+
+    #[test]
+    fn adversarial_classifier() {
+        let x = vec![
+            vec![-1.0, -1.0],
+            vec![-0.8, -1.2],
+            vec![-1.2, -0.7],
+            vec![1.0, 1.0],
+            vec![0.9, 1.2],
+            vec![1.1, 0.8],
+        ];
+        let y = vec![0, 0, 0, 1, 1, 1];
+
+        let mut classifier = MlpClassifier::new(vec![8])
+            .with_epochs(200)
+            .with_adversarial_training(0.2);
+        classifier.fit(&x, &y);
+
+        assert_eq!(classifier.score(&x, &y), 1.0);
+    }
+}
@@ -0,0 +1,84 @@
+//! Attributing a model's predictions to its input features.
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{mlp::Mlp, optim::standard_normal, val::Val};
+
+/// `|∂output/∂x_i|` for every feature of `x`, where the output is the highest one of the model
+/// for `x`, i.e. its predicted class. The larger the value, the more a small change of the
+/// feature moves the prediction.
+pub fn saliency(model: &Mlp, x: &[f64]) -> Vec<f64> {
+    input_gradients(model, x, model.predict_class(x))
+        .iter()
+        .map(|g| g.abs())
+        .collect()
+}
+
+/// [`saliency`] averaged over `samples` copies of `x` with Gaussian noise of standard deviation
+/// `noise` added to every feature, as in "SmoothGrad: removing noise by adding noise" (Smilkov
+/// et al.). The output is the one predicted for `x` itself in every copy. Averaging smooths out
+/// the local, noisy variations of the gradient.
+pub fn smooth_grad(model: &Mlp, x: &[f64], samples: usize, noise: f64, seed: u64) -> Vec<f64> {
+    assert!(samples > 0, "smooth_grad needs at least one sample");
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let output = model.predict_class(x);
+    let mut total = vec![0.0; x.len()];
+    for _ in 0..samples {
+        let noisy: Vec<f64> = x
+            .iter()
+            .map(|x| x + noise * standard_normal(&mut rng))
+            .collect();
+        for (t, g) in total.iter_mut().zip(input_gradients(model, &noisy, output)) {
+            *t += g.abs();
+        }
+    }
+
+    total.iter().map(|t| t / samples as f64).collect()
+}
+
+/// The gradient of the `output`-th output of `model` with respect to each feature of `x`,
+/// leaving the gradients of the model's parameters as they are.
+fn input_gradients(model: &Mlp, x: &[f64], output: usize) -> Vec<f64> {
+    let inputs: Vec<Val> = x.iter().map(|x| Val::from(*x)).collect();
+    let outputs = model.forward_vals(&inputs);
+    outputs[output].backward_wrt(&inputs);
+
+    let gradients = inputs.iter().map(Val::gradient).collect();
+    outputs[output].free_graph();
+    gradients
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{saliency, smooth_grad};
+    use crate::{mlp::Mlp, neuron::Activation};
+
+    #[test]
+    fn linear_saliency() {
+        // The gradient of a linear output is its weights.
+        let model = Mlp::new(3, vec![2]).with_output_activation(Activation::Linear);
+        let x = [0.5, -1.0, 2.0];
+        let class = model.predict_class(&x);
+        let weights: Vec<f64> = model.parameters()[class * 4..class * 4 + 3]
+            .iter()
+            .map(|w| w.data().abs())
+            .collect();
+
+        assert_eq!(saliency(&model, &x), weights);
+        for (s, w) in smooth_grad(&model, &x, 5, 0.0, 1).iter().zip(&weights) {
+            assert!((s - w).abs() < 1e-12);
+        }
+        assert!(model.parameters().iter().all(|p| p.gradient() == 0.0));
+    }
+
+    #[test]
+    fn smoothed_saliency() {
+        let model = Mlp::new(2, vec![8, 3]);
+        let x = [0.3, -0.4];
+
+        let smoothed = smooth_grad(&model, &x, 20, 0.1, 7);
+        assert_eq!(smoothed, smooth_grad(&model, &x, 20, 0.1, 7));
+        assert_eq!(smoothed.len(), 2);
+        assert!(smoothed.iter().all(|s| s.is_finite() && *s >= 0.0));
+    }
+}
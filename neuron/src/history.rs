@@ -0,0 +1,219 @@
+//! Recording a training run and sharing it as a single-file HTML report.
+//!
+//! ```no_run
+//! use neuron::history::History;
+//!
+//! let mut history = History::new().with_config("learning rate", 0.05);
+//! for epoch in 0..10 {
+//!     history.push("loss", 1.0 / (epoch + 1) as f64);
+//! }
+//! history.set_evaluation("accuracy", 0.9);
+//! history.to_html("report.html").unwrap();
+//! ```
+use std::{collections::BTreeMap, fmt::Display, fs, io, path::Path};
+
+use crate::val::Val;
+
+const CHART_WIDTH: usize = 480;
+const CHART_HEIGHT: usize = 200;
+
+/// The configuration, per-epoch series (losses, metrics) and final evaluation of a training
+/// run.
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    config: Vec<(String, String)>,
+    series: BTreeMap<String, Vec<f64>>,
+    evaluation: Vec<(String, f64)>,
+    graph: Option<String>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a setting of the run, e.g. the learning rate.
+    pub fn with_config(mut self, name: &str, value: impl Display) -> Self {
+        self.config.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Embed a thumbnail of the graph of `val`, usually the loss, in the report.
+    pub fn with_graph(mut self, val: &Val) -> Self {
+        self.graph = Some(val.to_svg(CHART_WIDTH, CHART_HEIGHT));
+        self
+    }
+
+    /// Append the value of `name` for the next epoch.
+    pub fn push(&mut self, name: &str, value: f64) {
+        self.series.entry(name.to_string()).or_default().push(value);
+    }
+
+    /// Every value pushed for `name`, one per epoch.
+    pub fn series(&self, name: &str) -> Option<&[f64]> {
+        self.series.get(name).map(Vec::as_slice)
+    }
+
+    /// Record a result of the final evaluation, replacing any earlier one of the same name.
+    pub fn set_evaluation(&mut self, name: &str, value: f64) {
+        match self.evaluation.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value,
+            None => self.evaluation.push((name.to_string(), value)),
+        }
+    }
+
+    /// Write the report to `path`.
+    pub fn to_html(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.render_html())
+    }
+
+    /// A titled line chart per series, as HTML.
+    pub(crate) fn charts_html(&self) -> String {
+        let mut out = String::new();
+        for (name, values) in &self.series {
+            out.push_str(&format!("<h3>{}</h3>\n", escape(name)));
+            out.push_str(&chart(values));
+        }
+        out
+    }
+
+    /// The report as a self-contained HTML page: no scripts, charts are inline SVG.
+    pub fn render_html(&self) -> String {
+        let mut out = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Training report</title>\n<style>\n\
+             body { font-family: sans-serif; margin: 2em; }\n\
+             table { border-collapse: collapse; }\n\
+             td, th { border: 1px solid lightgray; padding: 4px 8px; text-align: left; }\n\
+             </style>\n</head>\n<body>\n<h1>Training report</h1>\n",
+        );
+
+        if !self.config.is_empty() {
+            out.push_str("<h2>Configuration</h2>\n");
+            out.push_str(&table(
+                self.config.iter().map(|(n, v)| (n.as_str(), v.clone())),
+            ));
+        }
+
+        if !self.series.is_empty() {
+            out.push_str("<h2>Training</h2>\n");
+            out.push_str(&self.charts_html());
+        }
+
+        if !self.evaluation.is_empty() {
+            out.push_str("<h2>Evaluation</h2>\n");
+            out.push_str(&table(
+                self.evaluation
+                    .iter()
+                    .map(|(n, v)| (n.as_str(), format!("{v:.4}"))),
+            ));
+        }
+
+        if let Some(graph) = &self.graph {
+            out.push_str("<h2>Graph</h2>\n");
+            out.push_str(graph);
+        }
+
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn table<'a>(rows: impl Iterator<Item = (&'a str, String)>) -> String {
+    let mut out = String::from("<table>\n");
+    for (name, value) in rows {
+        out.push_str(&format!(
+            "<tr><th>{}</th><td>{}</td></tr>\n",
+            escape(name),
+            escape(&value)
+        ));
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+/// A line chart of `values` over the epochs, with the range of the values on the left.
+/// Non-finite values are left out.
+fn chart(values: &[f64]) -> String {
+    const MARGIN: f64 = 50.0;
+
+    let finite = values.iter().filter(|v| v.is_finite());
+    let min = finite.clone().fold(f64::INFINITY, |m, v| m.min(*v));
+    let max = finite.fold(f64::NEG_INFINITY, |m, v| m.max(*v));
+    let range = if max > min { max - min } else { 1.0 };
+
+    let width = CHART_WIDTH as f64 - MARGIN;
+    let height = CHART_HEIGHT as f64 - 20.0;
+    let points: Vec<String> = values
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| v.is_finite())
+        .map(|(i, v)| {
+            let x = MARGIN + width * i as f64 / (values.len().max(2) - 1) as f64;
+            let y = 10.0 + height * (max - v) / range;
+            format!("{x:.1},{y:.1}")
+        })
+        .collect();
+
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{CHART_WIDTH}\" \
+         height=\"{CHART_HEIGHT}\" font-family=\"sans-serif\" font-size=\"11\">\n"
+    );
+    if !points.is_empty() {
+        out.push_str(&format!(
+            "<text x=\"{}\" y=\"14\" text-anchor=\"end\">{max:.4}</text>\n\
+             <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{min:.4}</text>\n",
+            MARGIN - 4.0,
+            MARGIN - 4.0,
+            10.0 + height
+        ));
+        out.push_str(&format!(
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"steelblue\"/>\n",
+            points.join(" ")
+        ));
+    }
+    out.push_str(&format!(
+        "<line x1=\"{MARGIN}\" y1=\"{:.1}\" x2=\"{CHART_WIDTH}\" y2=\"{:.1}\" stroke=\"gray\"/>\n",
+        10.0 + height,
+        10.0 + height
+    ));
+    out.push_str("</svg>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use crate::val::Val;
+
+    #[test]
+    fn html_report() {
+        let mut history = History::new()
+            .with_config("learning rate", 0.05)
+            .with_config("layers", "<2, 1>");
+        for loss in [1.0, 0.5, 0.25] {
+            history.push("loss", loss);
+        }
+        history.set_evaluation("accuracy", 0.5);
+        history.set_evaluation("accuracy", 0.75);
+
+        let a = Val::new(2.0, "a");
+        let history = history.with_graph(&(a.clone() * a.relu()));
+
+        assert_eq!(history.series("loss"), Some(&[1.0, 0.5, 0.25][..]));
+        let html = history.render_html();
+        assert!(html.contains("<td>0.05</td>"));
+        assert!(html.contains("&lt;2, 1&gt;"));
+        assert!(html.contains("<td>0.7500</td>"));
+        assert!(!html.contains("0.5000</td>"));
+        assert!(html.contains("<polyline"));
+        assert_eq!(html.matches("<circle").count(), 3);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{neuron::Neuron, val::Val};
+use crate::{
+    neuron::{Activation, Neuron},
+    val::Val,
+};
 
 /// A layer of neurons.
 pub struct Layer {
@@ -12,7 +15,57 @@ impl Layer {
         }
     }
 
+    pub fn with_activation(mut self, activation: Activation) -> Self {
+        self.neurons = self
+            .neurons
+            .into_iter()
+            .map(|n| n.with_activation(activation))
+            .collect();
+        self
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.neurons.first().map_or(0, Neuron::num_inputs)
+    }
+
+    pub fn num_outputs(&self) -> usize {
+        self.neurons.len()
+    }
+
+    pub fn neurons(&self) -> &[Neuron] {
+        &self.neurons
+    }
+
+    pub fn activation(&self) -> Activation {
+        self.neurons
+            .first()
+            .map_or(Activation::default(), Neuron::activation)
+    }
+
     pub fn forward(&self, inputs: &[Val]) -> Vec<Val> {
         self.neurons.iter().map(|n| n.forward(inputs)).collect()
     }
+
+    pub fn apply_max_norm(&self, max_norm: f64) {
+        for n in &self.neurons {
+            n.apply_max_norm(max_norm);
+        }
+    }
+
+    pub fn parameters(&self) -> Vec<Val> {
+        self.neurons.iter().flat_map(|n| n.parameters()).collect()
+    }
+
+    /// [`Layer::parameters`] with their names, e.g. `neurons.2.bias`.
+    pub fn named_parameters(&self) -> Vec<(String, Val)> {
+        self.neurons
+            .iter()
+            .enumerate()
+            .flat_map(|(i, n)| {
+                n.named_parameters()
+                    .into_iter()
+                    .map(move |(name, p)| (format!("neurons.{i}.{name}"), p))
+            })
+            .collect()
+    }
 }
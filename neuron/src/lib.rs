@@ -1,5 +1,34 @@
+pub mod attack;
+pub mod audit;
+pub mod autoencoder;
+pub mod callback;
+pub mod charlm;
+pub mod datasets;
+pub mod decode;
+pub mod display;
+pub mod ensemble;
+pub mod estimator;
+pub mod explain;
+pub mod history;
 pub mod layer;
+pub mod loader;
+pub mod loss;
+pub mod metrics;
 pub mod mlp;
 pub mod network;
 pub mod neuron;
+#[cfg(feature = "notebook")]
+pub mod notebook;
+pub mod ops;
+pub mod optim;
+pub mod parse;
+pub mod perturb;
+pub mod profiler;
+pub mod prune;
+pub mod schedule;
+pub mod sequence;
+pub mod sparse;
+pub mod testing;
+pub mod trace;
+pub mod transformer;
 pub mod val;
@@ -0,0 +1,290 @@
+//! Batching samples for training, in an order decided by a [`Sampler`].
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+/// Decides which samples an epoch visits, and in which order.
+pub trait Sampler {
+    /// The indices of the samples, out of `len`, to visit during `epoch`.
+    fn order(&mut self, len: usize, epoch: usize) -> Vec<usize>;
+}
+
+/// Every sample once, in the order of the dataset.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SequentialSampler;
+
+impl Sampler for SequentialSampler {
+    fn order(&mut self, len: usize, _epoch: usize) -> Vec<usize> {
+        (0..len).collect()
+    }
+}
+
+/// Every sample once, in an order that is random but only depends on the seed and the epoch.
+#[derive(Clone, Copy, Debug)]
+pub struct ShuffledSampler {
+    seed: u64,
+}
+
+impl ShuffledSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+impl Sampler for ShuffledSampler {
+    fn order(&mut self, len: usize, epoch: usize) -> Vec<usize> {
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(epoch as u64));
+        let mut order: Vec<usize> = (0..len).collect();
+        order.shuffle(&mut rng);
+        order
+    }
+}
+
+/// As many samples as the dataset holds, drawn with replacement with probabilities
+/// proportional to their weights, e.g. to oversample rare classes.
+#[derive(Clone, Debug)]
+pub struct WeightedSampler {
+    weights: Vec<f64>,
+    seed: u64,
+}
+
+impl WeightedSampler {
+    pub fn new(weights: Vec<f64>, seed: u64) -> Self {
+        assert!(
+            weights.iter().all(|w| *w >= 0.0) && weights.iter().any(|w| *w > 0.0),
+            "weights must be non negative and not all zero"
+        );
+        Self { weights, seed }
+    }
+}
+
+impl Sampler for WeightedSampler {
+    fn order(&mut self, len: usize, epoch: usize) -> Vec<usize> {
+        assert_eq!(len, self.weights.len(), "every sample needs a weight");
+
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(epoch as u64));
+        let cumulative: Vec<f64> = self
+            .weights
+            .iter()
+            .scan(0.0, |total, w| {
+                *total += w;
+                Some(*total)
+            })
+            .collect();
+        let total = cumulative[len - 1];
+
+        (0..len)
+            .map(|_| {
+                let target = rng.gen_range(0.0..total);
+                // The first sample whose cumulative weight goes past the target.
+                cumulative.partition_point(|c| *c <= target).min(len - 1)
+            })
+            .collect()
+    }
+}
+
+/// Every sample once, easiest first, according to a difficulty callback that gets the index of
+/// the sample and the epoch. Samples of equal difficulty keep their dataset order.
+pub struct CurriculumSampler {
+    difficulty: Box<dyn FnMut(usize, usize) -> f64>,
+}
+
+impl CurriculumSampler {
+    pub fn new(difficulty: impl FnMut(usize, usize) -> f64 + 'static) -> Self {
+        Self {
+            difficulty: Box::new(difficulty),
+        }
+    }
+}
+
+impl Sampler for CurriculumSampler {
+    fn order(&mut self, len: usize, epoch: usize) -> Vec<usize> {
+        let difficulties: Vec<f64> = (0..len).map(|i| (self.difficulty)(i, epoch)).collect();
+        let mut order: Vec<usize> = (0..len).collect();
+        order.sort_by(|a, b| difficulties[*a].total_cmp(&difficulties[*b]));
+        order
+    }
+}
+
+/// Every sample once, spread out so that every class is evenly distributed over the epoch and
+/// each batch keeps roughly the class proportions of the dataset.
+#[derive(Clone, Debug)]
+pub struct StratifiedSampler {
+    labels: Vec<usize>,
+    seed: u64,
+}
+
+impl StratifiedSampler {
+    /// `labels` holds the class of each sample.
+    pub fn new(labels: Vec<usize>, seed: u64) -> Self {
+        Self { labels, seed }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn order(&mut self, len: usize, epoch: usize) -> Vec<usize> {
+        assert_eq!(len, self.labels.len(), "every sample needs a label");
+
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(epoch as u64));
+        // The k-th of the n samples of a class goes at a random spot of the k-th n-th of the
+        // epoch.
+        let mut keyed: Vec<(f64, usize)> = vec![];
+        for mut class in by_class(&self.labels) {
+            class.shuffle(&mut rng);
+            let n = class.len() as f64;
+            for (k, i) in class.into_iter().enumerate() {
+                keyed.push(((k as f64 + rng.gen::<f64>()) / n, i));
+            }
+        }
+
+        keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+        keyed.into_iter().map(|(_, i)| i).collect()
+    }
+}
+
+/// The indices of the samples of each class, in order of class.
+fn by_class(labels: &[usize]) -> Vec<Vec<usize>> {
+    let num_classes = labels.iter().max().map_or(0, |m| m + 1);
+    let mut classes = vec![vec![]; num_classes];
+    for (i, label) in labels.iter().enumerate() {
+        classes[*label].push(i);
+    }
+    classes
+}
+
+/// Split the indices of the samples into training and validation sets, putting a
+/// `val_fraction` of every class, rounded, in the validation set. Both sets are sorted.
+pub fn stratified_split(
+    labels: &[usize],
+    val_fraction: f64,
+    seed: u64,
+) -> (Vec<usize>, Vec<usize>) {
+    assert!(
+        (0.0..=1.0).contains(&val_fraction),
+        "val_fraction must be between 0 and 1"
+    );
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut train = vec![];
+    let mut val = vec![];
+    for mut class in by_class(labels) {
+        class.shuffle(&mut rng);
+        let num_val = (class.len() as f64 * val_fraction).round() as usize;
+        val.extend_from_slice(&class[..num_val]);
+        train.extend_from_slice(&class[num_val..]);
+    }
+
+    train.sort();
+    val.sort();
+    (train, val)
+}
+
+/// Splits a dataset into batches, one epoch at a time.
+pub struct DataLoader<T> {
+    samples: Vec<T>,
+    batch_size: usize,
+    sampler: Box<dyn Sampler>,
+    epoch: usize,
+}
+
+impl<T> DataLoader<T> {
+    /// Batches of `batch_size` samples in dataset order, the last batch of an epoch may be
+    /// smaller.
+    pub fn new(samples: Vec<T>, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batches need at least one sample");
+        Self {
+            samples,
+            batch_size,
+            sampler: Box::new(SequentialSampler),
+            epoch: 0,
+        }
+    }
+
+    pub fn with_sampler(mut self, sampler: impl Sampler + 'static) -> Self {
+        self.sampler = Box::new(sampler);
+        self
+    }
+
+    pub fn samples(&self) -> &[T] {
+        &self.samples
+    }
+
+    /// The number of epochs returned so far.
+    pub fn epoch(&self) -> usize {
+        self.epoch
+    }
+
+    /// The batches of the next epoch.
+    pub fn next_epoch(&mut self) -> Vec<Vec<&T>> {
+        let order = self.sampler.order(self.samples.len(), self.epoch);
+        self.epoch += 1;
+
+        order
+            .chunks(self.batch_size)
+            .map(|batch| batch.iter().map(|i| &self.samples[*i]).collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        stratified_split, CurriculumSampler, DataLoader, Sampler, SequentialSampler,
+        ShuffledSampler, StratifiedSampler, WeightedSampler,
+    };
+
+    #[test]
+    fn batches() {
+        let mut loader = DataLoader::new(vec!['a', 'b', 'c', 'd', 'e'], 2);
+        assert_eq!(
+            loader.next_epoch(),
+            vec![vec![&'a', &'b'], vec![&'c', &'d'], vec![&'e']]
+        );
+        assert_eq!(loader.epoch(), 1);
+    }
+
+    #[test]
+    fn samplers() {
+        assert_eq!(SequentialSampler.order(3, 0), vec![0, 1, 2]);
+
+        let mut shuffled = ShuffledSampler::new(42);
+        let first = shuffled.order(20, 0);
+        assert_eq!(first, ShuffledSampler::new(42).order(20, 0));
+        assert_ne!(first, shuffled.order(20, 1));
+        let mut sorted = first.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+
+        let order = WeightedSampler::new(vec![0.0, 1.0, 0.0, 3.0], 7).order(4, 0);
+        assert!(order.iter().all(|i| *i == 1 || *i == 3));
+
+        let mut curriculum = CurriculumSampler::new(|i, _| [0.5, 0.1, 0.9, 0.1][i]);
+        assert_eq!(curriculum.order(4, 0), vec![1, 3, 0, 2]);
+    }
+
+    #[test]
+    fn loader_with_sampler() {
+        let mut loader =
+            DataLoader::new((0..10).collect::<Vec<_>>(), 4).with_sampler(ShuffledSampler::new(1));
+        let mut again =
+            DataLoader::new((0..10).collect::<Vec<_>>(), 4).with_sampler(ShuffledSampler::new(1));
+
+        assert_eq!(loader.next_epoch(), again.next_epoch());
+        assert_eq!(loader.next_epoch().len(), 3);
+    }
+
+    #[test]
+    fn stratified() {
+        // 12 samples of class 0, 4 of class 1.
+        let labels: Vec<usize> = (0..16).map(|i| usize::from(i % 4 == 3)).collect();
+
+        let (train, val) = stratified_split(&labels, 0.25, 3);
+        assert_eq!(val.len(), 4);
+        assert_eq!(val.iter().filter(|i| labels[**i] == 1).count(), 1);
+        assert_eq!(train.len(), 12);
+        assert!(train.iter().all(|i| !val.contains(i)));
+
+        let order = StratifiedSampler::new(labels.clone(), 5).order(16, 0);
+        for batch in order.chunks(4) {
+            assert_eq!(batch.iter().filter(|i| labels[**i] == 1).count(), 1);
+        }
+    }
+}
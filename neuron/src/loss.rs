@@ -0,0 +1,370 @@
+//! Loss functions over a batch of samples.
+//!
+//! Every loss takes the model outputs of each sample of the batch, and a [`Reduction`] deciding
+//! whether the per-sample losses are returned as is or combined into a single node.
+use crate::{
+    ops::{cosine_similarity, log_softmax},
+    val::Val,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Reduction {
+    /// The average over the batch, as a single node.
+    #[default]
+    Mean,
+    /// The total over the batch, as a single node.
+    Sum,
+    /// One node per sample, e.g. for custom weighting.
+    None,
+}
+
+impl Reduction {
+    /// Combine per-sample `losses`. [`Reduction::None`] returns them unchanged, the others
+    /// return a single node, 0 for an empty batch.
+    pub fn reduce(self, losses: Vec<Val>) -> Vec<Val> {
+        if self == Reduction::None {
+            return losses;
+        }
+
+        let n = losses.len();
+        let mut total = Val::from(0.0);
+        for l in losses {
+            total.accumulate(l);
+        }
+
+        match self {
+            Reduction::Mean if n > 0 => vec![total * Val::from(1.0 / n as f64)],
+            _ => vec![total],
+        }
+    }
+}
+
+/// Scale per-sample `losses`, as returned with [`Reduction::None`], by constant `weights`
+/// before reducing them. [`Reduction::Mean`] divides by the total weight rather than the
+/// number of samples.
+pub fn weighted(losses: Vec<Val>, weights: &[f64], reduction: Reduction) -> Vec<Val> {
+    assert_eq!(losses.len(), weights.len(), "every sample needs a weight");
+
+    let weighted = losses
+        .into_iter()
+        .zip(weights)
+        .map(|(l, w)| l * Val::from(*w))
+        .collect();
+
+    let total_weight: f64 = weights.iter().sum();
+    match reduction {
+        Reduction::Mean if total_weight != 0.0 => {
+            let total = Reduction::Sum.reduce(weighted).remove(0);
+            vec![total * Val::from(1.0 / total_weight)]
+        }
+        Reduction::Mean => Reduction::Sum.reduce(weighted),
+        _ => reduction.reduce(weighted),
+    }
+}
+
+/// The mean squared error of each sample, averaged over its outputs.
+pub fn mse(predictions: &[Vec<Val>], targets: &[Vec<f64>], reduction: Reduction) -> Vec<Val> {
+    assert_eq!(
+        predictions.len(),
+        targets.len(),
+        "every sample needs a target"
+    );
+
+    let losses = predictions
+        .iter()
+        .zip(targets)
+        .map(|(prediction, target)| {
+            assert_eq!(
+                prediction.len(),
+                target.len(),
+                "every output needs a target"
+            );
+
+            let mut total = Val::from(0.0);
+            for (p, t) in prediction.iter().zip(target) {
+                total.accumulate((p.clone() + -Val::from(*t)).powi(2));
+            }
+            total * Val::from(1.0 / prediction.len() as f64)
+        })
+        .collect();
+
+    reduction.reduce(losses)
+}
+
+/// The mean absolute error of each sample, averaged over its outputs.
+pub fn mae(predictions: &[Vec<Val>], targets: &[Vec<f64>], reduction: Reduction) -> Vec<Val> {
+    assert_eq!(
+        predictions.len(),
+        targets.len(),
+        "every sample needs a target"
+    );
+
+    let losses = predictions
+        .iter()
+        .zip(targets)
+        .map(|(prediction, target)| {
+            assert_eq!(
+                prediction.len(),
+                target.len(),
+                "every output needs a target"
+            );
+
+            let mut total = Val::from(0.0);
+            for (p, t) in prediction.iter().zip(target) {
+                total.accumulate((p - *t).abs());
+            }
+            total / prediction.len() as f64
+        })
+        .collect();
+
+    reduction.reduce(losses)
+}
+
+/// The multi-class hinge loss of each sample: every other class has to score at least 1 below
+/// the right one.
+pub fn hinge(scores: &[Vec<Val>], classes: &[usize], reduction: Reduction) -> Vec<Val> {
+    assert_eq!(scores.len(), classes.len(), "every sample needs a class");
+
+    let losses = scores
+        .iter()
+        .zip(classes)
+        .map(|(scores, &class)| {
+            let mut total = Val::from(0.0);
+            for (j, s) in scores.iter().enumerate() {
+                if j != class {
+                    total.accumulate((s.clone() + -scores[class].clone() + Val::from(1.0)).relu());
+                }
+            }
+            total
+        })
+        .collect();
+
+    reduction.reduce(losses)
+}
+
+/// The cross-entropy of each sample: minus the log of the softmax probability of its class,
+/// taken in log space so large logits can't overflow.
+pub fn cross_entropy(logits: &[Vec<Val>], classes: &[usize], reduction: Reduction) -> Vec<Val> {
+    assert_eq!(logits.len(), classes.len(), "every sample needs a class");
+
+    let losses = logits
+        .iter()
+        .zip(classes)
+        .map(|(logits, &class)| -&log_softmax(logits)[class])
+        .collect();
+
+    reduction.reduce(losses)
+}
+
+/// The Kullback-Leibler divergence `KL(q || p)` of each sample, from the target distribution
+/// `q` to the softmax `p` of the logits, in nats. Targets of 0 contribute nothing, and the
+/// softmax is taken in log space so large logits can't overflow.
+pub fn kl_div(logits: &[Vec<Val>], targets: &[Vec<f64>], reduction: Reduction) -> Vec<Val> {
+    assert_eq!(logits.len(), targets.len(), "every sample needs a target");
+
+    let losses = logits
+        .iter()
+        .zip(targets)
+        .map(|(logits, target)| {
+            assert_eq!(
+                logits.len(),
+                target.len(),
+                "every logit needs a target probability"
+            );
+
+            let mut total = Val::from(0.0);
+            for (log_p, &q) in log_softmax(logits).iter().zip(target) {
+                if q > 0.0 {
+                    // q (ln q - ln p)
+                    total.accumulate(q * (q.ln() - log_p));
+                }
+            }
+            total
+        })
+        .collect();
+
+    reduction.reduce(losses)
+}
+
+/// The contrastive loss of each pair of embeddings `(a[i], b[i])` under the cosine distance
+/// `d = 1 - cos`: `d²` for pairs marked `similar`, `max(0, margin - d)²` for the others, so
+/// dissimilar pairs are only pushed apart until they are `margin` away.
+pub fn contrastive(
+    a: &[Vec<Val>],
+    b: &[Vec<Val>],
+    similar: &[bool],
+    margin: f64,
+    reduction: Reduction,
+) -> Vec<Val> {
+    assert_eq!(a.len(), b.len(), "every embedding needs a partner");
+    assert_eq!(a.len(), similar.len(), "every pair needs a label");
+
+    let losses = a
+        .iter()
+        .zip(b)
+        .zip(similar)
+        .map(|((a, b), &similar)| {
+            let distance = 1.0 - cosine_similarity(a, b);
+            if similar {
+                distance.powi(2)
+            } else {
+                (margin - distance).relu().powi(2)
+            }
+        })
+        .collect();
+
+    reduction.reduce(losses)
+}
+
+/// The triplet loss of each `(anchor, positive, negative)` under the cosine distance: the
+/// positive has to be closer to the anchor than the negative by at least `margin`.
+pub fn triplet(
+    anchors: &[Vec<Val>],
+    positives: &[Vec<Val>],
+    negatives: &[Vec<Val>],
+    margin: f64,
+    reduction: Reduction,
+) -> Vec<Val> {
+    assert_eq!(
+        anchors.len(),
+        positives.len(),
+        "every anchor needs a positive"
+    );
+    assert_eq!(
+        anchors.len(),
+        negatives.len(),
+        "every anchor needs a negative"
+    );
+
+    let losses = anchors
+        .iter()
+        .zip(positives)
+        .zip(negatives)
+        .map(|((anchor, positive), negative)| {
+            // (1 - cos(a, p)) - (1 - cos(a, n)) + margin
+            (cosine_similarity(anchor, negative) - cosine_similarity(anchor, positive) + margin)
+                .relu()
+        })
+        .collect();
+
+    reduction.reduce(losses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        contrastive, cross_entropy, hinge, kl_div, mae, mse, triplet, weighted, Reduction,
+    };
+    use crate::val::Val;
+
+    fn vals(xs: &[f64]) -> Vec<Val> {
+        xs.iter().map(|x| Val::from(*x)).collect()
+    }
+
+    #[test]
+    fn reductions() {
+        let predictions = vec![vals(&[1.0, 2.0]), vals(&[0.0, 0.0])];
+        let targets = vec![vec![1.0, 0.0], vec![3.0, 1.0]];
+
+        let data = |losses: Vec<Val>| losses.iter().map(Val::data).collect::<Vec<_>>();
+        assert_eq!(
+            data(mse(&predictions, &targets, Reduction::None)),
+            vec![2.0, 5.0]
+        );
+        assert_eq!(data(mse(&predictions, &targets, Reduction::Sum)), vec![7.0]);
+        assert_eq!(
+            data(mse(&predictions, &targets, Reduction::Mean)),
+            vec![3.5]
+        );
+        assert_eq!(data(Reduction::Mean.reduce(vec![])), vec![0.0]);
+        assert_eq!(
+            data(mae(&predictions, &targets, Reduction::None)),
+            vec![1.0, 2.0]
+        );
+    }
+
+    #[test]
+    fn hinge_loss() {
+        let scores = vec![vals(&[2.0, 0.5, 1.5]), vals(&[0.0, 3.0, 0.0])];
+
+        let losses = hinge(&scores, &[0, 1], Reduction::None);
+        assert_eq!(losses[0].data(), 0.5);
+        assert_eq!(losses[1].data(), 0.0);
+
+        let loss = hinge(&scores, &[0, 1], Reduction::Mean).remove(0);
+        loss.back_prop_gradient();
+        assert_eq!(scores[0][0].gradient(), -0.5);
+        assert_eq!(scores[0][2].gradient(), 0.5);
+        assert_eq!(scores[0][1].gradient(), 0.0);
+    }
+
+    #[test]
+    fn weighted_losses() {
+        let losses = vals(&[1.0, 4.0]);
+
+        let mean = weighted(losses.clone(), &[3.0, 1.0], Reduction::Mean).remove(0);
+        assert_eq!(mean.data(), 7.0 / 4.0);
+        assert_eq!(
+            weighted(losses.clone(), &[3.0, 1.0], Reduction::Sum)[0].data(),
+            7.0
+        );
+
+        mean.back_prop_gradient();
+        assert_eq!(losses[0].gradient(), 0.75);
+        assert_eq!(losses[1].gradient(), 0.25);
+    }
+
+    #[test]
+    fn metric_learning_losses() {
+        let a = vec![vals(&[1.0, 0.0]), vals(&[1.0, 0.0])];
+        let b = vec![vals(&[0.0, 1.0]), vals(&[0.0, 1.0])];
+
+        // Orthogonal vectors are at distance 1.
+        let losses = contrastive(&a, &b, &[true, false], 1.5, Reduction::None);
+        assert!((losses[0].data() - 1.0).abs() < 1e-9);
+        assert!((losses[1].data() - 0.25).abs() < 1e-9);
+
+        let anchors = vec![vals(&[1.0, 0.0])];
+        let close = vec![vals(&[1.0, 0.1])];
+        let far = vec![vals(&[-1.0, 0.5])];
+        assert_eq!(
+            triplet(&anchors, &close, &far, 0.5, Reduction::Sum)[0].data(),
+            0.0
+        );
+        let loss = triplet(&anchors, &far, &close, 0.5, Reduction::Sum).remove(0);
+        assert!(loss.data() > 2.0);
+
+        loss.back_prop_gradient();
+        // Moving the bad positive towards the anchor lowers the loss.
+        assert!(far[0][0].gradient() < 0.0);
+    }
+
+    #[test]
+    fn kl_divergence() {
+        let logits = vec![vals(&[0.0, 0.0]), vals(&[2.0, 0.0])];
+        let targets = vec![vec![0.5, 0.5], vec![1.0, 0.0]];
+
+        let losses = kl_div(&logits, &targets, Reduction::None);
+        assert!(losses[0].data().abs() < 1e-12);
+        let p = 1.0 / (1.0 + (-2.0f64).exp());
+        assert!((losses[1].data() + p.ln()).abs() < 1e-12);
+
+        losses[1].back_prop_gradient();
+        // The gradient of cross-entropy on logits: p - q.
+        assert!((logits[1][0].gradient() - (p - 1.0)).abs() < 1e-12);
+        assert!((logits[1][1].gradient() - (1.0 - p)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn cross_entropy_loss() {
+        let logits = vec![vals(&[2.0, 0.0]), vals(&[1000.0, 0.0])];
+
+        let losses = cross_entropy(&logits, &[0, 0], Reduction::None);
+        let p = 1.0 / (1.0 + (-2.0f64).exp());
+        assert!((losses[0].data() + p.ln()).abs() < 1e-12);
+        assert_eq!(losses[1].data(), 0.0);
+
+        losses[0].back_prop_gradient();
+        assert!((logits[0][0].gradient() - (p - 1.0)).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,482 @@
+//! Evaluation metrics that are updated one batch at a time, so the predictions of a large
+//! validation set never have to be kept around.
+
+/// A metric accumulated over batches of samples.
+pub trait Metric {
+    type Sample;
+
+    fn update(&mut self, batch: &[Self::Sample]);
+
+    /// The value of the metric over every sample seen since the last reset.
+    fn value(&self) -> f64;
+
+    fn reset(&mut self);
+}
+
+/// Running mean and variance of e.g. the per sample losses, using Welford's algorithm.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunningMean {
+    count: usize,
+    mean: f64,
+    // Sum of the squared differences from the mean.
+    m2: f64,
+}
+
+impl RunningMean {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// The population variance, 0 until a sample has been seen.
+    pub fn variance(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.m2 / self.count as f64
+        }
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+}
+
+impl Metric for RunningMean {
+    type Sample = f64;
+
+    fn update(&mut self, batch: &[f64]) {
+        for x in batch {
+            self.count += 1;
+            let delta = x - self.mean;
+            self.mean += delta / self.count as f64;
+            self.m2 += delta * (x - self.mean);
+        }
+    }
+
+    fn value(&self) -> f64 {
+        self.mean
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Running fraction of `(prediction, label)` pairs that agree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunningAccuracy {
+    correct: usize,
+    total: usize,
+}
+
+impl RunningAccuracy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn correct(&self) -> usize {
+        self.correct
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
+
+impl Metric for RunningAccuracy {
+    type Sample = (usize, usize);
+
+    fn update(&mut self, batch: &[(usize, usize)]) {
+        self.total += batch.len();
+        self.correct += batch.iter().filter(|(p, l)| p == l).count();
+    }
+
+    /// 0 until a sample has been seen.
+    fn value(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.correct as f64 / self.total as f64
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Counts of `(prediction, label)` pairs over classes `0..num_classes`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfusionMatrix {
+    // Indexed by label then prediction.
+    counts: Vec<Vec<usize>>,
+}
+
+impl ConfusionMatrix {
+    pub fn new(num_classes: usize) -> Self {
+        Self {
+            counts: vec![vec![0; num_classes]; num_classes],
+        }
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// How many samples of class `label` were predicted as `prediction`.
+    pub fn count(&self, label: usize, prediction: usize) -> usize {
+        self.counts[label][prediction]
+    }
+
+    fn max_count(&self) -> usize {
+        self.counts.iter().flatten().copied().max().unwrap_or(0)
+    }
+
+    /// A text heatmap, one row per label and one column per prediction. Every count is prefixed
+    /// with a shade proportional to it.
+    pub fn to_ascii(&self) -> String {
+        const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+        let max = self.max_count().max(1);
+        let label_width = "label"
+            .len()
+            .max(self.num_classes().saturating_sub(1).to_string().len());
+        let digits = max
+            .to_string()
+            .len()
+            .max(self.num_classes().saturating_sub(1).to_string().len());
+
+        let mut out = format!("{:label_width$} predicted\n", "");
+        out.push_str(&format!("{:<label_width$}", "label"));
+        for prediction in 0..self.num_classes() {
+            out.push_str(&format!(" {prediction:>w$}", w = digits + 2));
+        }
+        out.push('\n');
+
+        for (label, row) in self.counts.iter().enumerate() {
+            out.push_str(&format!("{label:>label_width$}"));
+            for &count in row {
+                let shade = SHADES[count * (SHADES.len() - 1) / max];
+                out.push_str(&format!(" {shade} {count:>digits$}"));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// An SVG heatmap, one row per label and one column per prediction, darker cells hold more
+    /// samples.
+    pub fn to_svg(&self) -> String {
+        const CELL: usize = 40;
+        const MARGIN: usize = 60;
+
+        let n = self.num_classes();
+        let max = self.max_count().max(1);
+        let size = MARGIN + n * CELL;
+
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}\" height=\"{size}\" \
+             font-family=\"sans-serif\" font-size=\"12\">\n"
+        );
+        out.push_str(&format!(
+            "<text x=\"{}\" y=\"15\" text-anchor=\"middle\">predicted</text>\n",
+            MARGIN + n * CELL / 2
+        ));
+        out.push_str(&format!(
+            "<text x=\"15\" y=\"{0}\" text-anchor=\"middle\" transform=\"rotate(-90 15 {0})\">\
+             label</text>\n",
+            MARGIN + n * CELL / 2
+        ));
+
+        for i in 0..n {
+            let center = MARGIN + i * CELL + CELL / 2;
+            out.push_str(&format!(
+                "<text x=\"{center}\" y=\"{}\" text-anchor=\"middle\">{i}</text>\n",
+                MARGIN - 10
+            ));
+            out.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{i}</text>\n",
+                MARGIN - 10,
+                center + 4
+            ));
+        }
+
+        for (label, row) in self.counts.iter().enumerate() {
+            for (prediction, &count) in row.iter().enumerate() {
+                let intensity = count as f64 / max as f64;
+                let x = MARGIN + prediction * CELL;
+                let y = MARGIN + label * CELL;
+                let text = if intensity > 0.5 { "white" } else { "black" };
+
+                out.push_str(&format!(
+                    "<rect x=\"{x}\" y=\"{y}\" width=\"{CELL}\" height=\"{CELL}\" \
+                     fill=\"steelblue\" fill-opacity=\"{intensity:.3}\" stroke=\"lightgray\"/>\n"
+                ));
+                out.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" fill=\"{text}\">{count}</text>\n",
+                    x + CELL / 2,
+                    y + CELL / 2 + 4
+                ));
+            }
+        }
+
+        out.push_str("</svg>\n");
+        out
+    }
+}
+
+impl Metric for ConfusionMatrix {
+    type Sample = (usize, usize);
+
+    fn update(&mut self, batch: &[(usize, usize)]) {
+        for &(prediction, label) in batch {
+            self.counts[label][prediction] += 1;
+        }
+    }
+
+    /// The accuracy, 0 until a sample has been seen.
+    fn value(&self) -> f64 {
+        let total: usize = self.counts.iter().flatten().sum();
+        let correct: usize = (0..self.num_classes()).map(|i| self.counts[i][i]).sum();
+
+        if total == 0 {
+            0.0
+        } else {
+            correct as f64 / total as f64
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.num_classes());
+    }
+}
+
+/// A point of the ROC curve of a binary classifier.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RocPoint {
+    /// Samples scoring at least this much are predicted positive.
+    pub threshold: f64,
+    pub false_positive_rate: f64,
+    pub true_positive_rate: f64,
+}
+
+/// A point of the precision-recall curve of a binary classifier.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PrPoint {
+    /// Samples scoring at least this much are predicted positive.
+    pub threshold: f64,
+    pub recall: f64,
+    pub precision: f64,
+}
+
+/// Sweep the threshold over every distinct score, highest first, returning the threshold and
+/// the true and false positive counts at it.
+fn threshold_sweep(scores: &[f64], labels: &[bool]) -> Vec<(f64, usize, usize)> {
+    assert_eq!(scores.len(), labels.len(), "every score needs a label");
+
+    let mut samples: Vec<(f64, bool)> =
+        scores.iter().copied().zip(labels.iter().copied()).collect();
+    samples.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut sweep: Vec<(f64, usize, usize)> = vec![];
+    let (mut true_positives, mut false_positives) = (0, 0);
+    for (i, (score, label)) in samples.iter().enumerate() {
+        if *label {
+            true_positives += 1;
+        } else {
+            false_positives += 1;
+        }
+
+        // Samples with the same score are all on the same side of any threshold.
+        if samples.get(i + 1).is_none_or(|next| next.0 != *score) {
+            sweep.push((*score, true_positives, false_positives));
+        }
+    }
+    sweep
+}
+
+/// The ROC curve of `scores` against `labels`, from (0, 0) at an infinite threshold to (1, 1)
+/// at the lowest score.
+pub fn roc_curve(scores: &[f64], labels: &[bool]) -> Vec<RocPoint> {
+    let positives = labels.iter().filter(|l| **l).count() as f64;
+    let negatives = labels.len() as f64 - positives;
+    let rate = |count: usize, total: f64| {
+        if total == 0.0 {
+            0.0
+        } else {
+            count as f64 / total
+        }
+    };
+
+    std::iter::once(RocPoint {
+        threshold: f64::INFINITY,
+        false_positive_rate: 0.0,
+        true_positive_rate: 0.0,
+    })
+    .chain(
+        threshold_sweep(scores, labels)
+            .into_iter()
+            .map(|(threshold, tp, fp)| RocPoint {
+                threshold,
+                false_positive_rate: rate(fp, negatives),
+                true_positive_rate: rate(tp, positives),
+            }),
+    )
+    .collect()
+}
+
+/// The precision-recall curve of `scores` against `labels`, one point per distinct score,
+/// highest first.
+pub fn pr_curve(scores: &[f64], labels: &[bool]) -> Vec<PrPoint> {
+    let positives = labels.iter().filter(|l| **l).count() as f64;
+
+    threshold_sweep(scores, labels)
+        .into_iter()
+        .map(|(threshold, tp, fp)| PrPoint {
+            threshold,
+            recall: if positives == 0.0 {
+                0.0
+            } else {
+                tp as f64 / positives
+            },
+            precision: tp as f64 / (tp + fp) as f64,
+        })
+        .collect()
+}
+
+/// The area under a curve given as `(x, y)` points ordered by `x`, using the trapezoidal rule.
+pub fn auc(points: impl IntoIterator<Item = (f64, f64)>) -> f64 {
+    let points: Vec<(f64, f64)> = points.into_iter().collect();
+    points
+        .windows(2)
+        .map(|w| (w[1].0 - w[0].0) * (w[0].1 + w[1].1) / 2.0)
+        .sum()
+}
+
+/// The area under the ROC curve, the probability that a random positive scores higher than a
+/// random negative.
+pub fn roc_auc(scores: &[f64], labels: &[bool]) -> f64 {
+    auc(roc_curve(scores, labels)
+        .iter()
+        .map(|p| (p.false_positive_rate, p.true_positive_rate)))
+}
+
+/// The area under the precision-recall curve, from recall 0, where the precision is taken to
+/// be the one of the highest threshold, to recall 1.
+pub fn pr_auc(scores: &[f64], labels: &[bool]) -> f64 {
+    let curve = pr_curve(scores, labels);
+    let start = curve.first().map(|p| (0.0, p.precision));
+    auc(start
+        .into_iter()
+        .chain(curve.iter().map(|p| (p.recall, p.precision))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        pr_auc, pr_curve, roc_auc, roc_curve, ConfusionMatrix, Metric, RunningAccuracy, RunningMean,
+    };
+
+    #[test]
+    fn running_mean() {
+        let data = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+
+        let mut mean = RunningMean::new();
+        mean.update(&data[..3]);
+        mean.update(&data[3..]);
+
+        assert_eq!(mean.count(), 8);
+        assert_eq!(mean.value(), 5.0);
+        assert_eq!(mean.variance(), 4.0);
+        assert_eq!(mean.std_dev(), 2.0);
+
+        mean.reset();
+        assert_eq!(mean.count(), 0);
+        assert_eq!(mean.variance(), 0.0);
+    }
+
+    #[test]
+    fn running_accuracy() {
+        let mut accuracy = RunningAccuracy::new();
+        assert_eq!(accuracy.value(), 0.0);
+
+        accuracy.update(&[(0, 0), (1, 0)]);
+        accuracy.update(&[(2, 2), (1, 1)]);
+
+        assert_eq!(accuracy.value(), 0.75);
+    }
+
+    #[test]
+    fn confusion_matrix() {
+        let mut matrix = ConfusionMatrix::new(2);
+        matrix.update(&[(0, 0), (0, 0), (0, 0), (0, 0), (1, 0), (1, 1), (1, 1)]);
+
+        assert_eq!(matrix.count(0, 1), 1);
+        assert_eq!(matrix.value(), 6.0 / 7.0);
+        assert_eq!(
+            matrix.to_ascii(),
+            concat!(
+                "      predicted\n",
+                "label   0   1\n",
+                "    0 █ 4 ░ 1\n",
+                "    1   0 ▒ 2\n",
+            )
+        );
+
+        let svg = matrix.to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<rect").count(), 4);
+        assert!(svg.contains(r#"fill-opacity="0.500""#));
+    }
+
+    #[test]
+    fn roc() {
+        let scores = [0.1, 0.4, 0.35, 0.8];
+        let labels = [false, false, true, true];
+
+        let curve = roc_curve(&scores, &labels);
+        let rates: Vec<(f64, f64)> = curve
+            .iter()
+            .map(|p| (p.false_positive_rate, p.true_positive_rate))
+            .collect();
+        assert_eq!(
+            rates,
+            vec![(0.0, 0.0), (0.0, 0.5), (0.5, 0.5), (0.5, 1.0), (1.0, 1.0)]
+        );
+        assert_eq!(curve[1].threshold, 0.8);
+        assert_eq!(roc_auc(&scores, &labels), 0.75);
+
+        // Tied scores make a single diagonal step.
+        assert_eq!(roc_auc(&[0.5, 0.5], &[true, false]), 0.5);
+    }
+
+    #[test]
+    fn pr() {
+        let curve = pr_curve(&[0.1, 0.4, 0.35, 0.8], &[false, false, true, true]);
+        let points: Vec<(f64, f64)> = curve.iter().map(|p| (p.recall, p.precision)).collect();
+
+        assert_eq!(
+            points,
+            vec![(0.5, 1.0), (0.5, 0.5), (1.0, 2.0 / 3.0), (1.0, 0.5)]
+        );
+        // 0.5 at precision 1, then from (0.5, 0.5) to (1, 2/3).
+        let area = pr_auc(&[0.1, 0.4, 0.35, 0.8], &[false, false, true, true]);
+        assert!((area - (0.5 + 0.5 * (0.5 + 2.0 / 3.0) / 2.0)).abs() < 1e-12);
+
+        assert_eq!(
+            pr_auc(&[0.8, 0.7, 0.2, 0.1], &[true, true, false, false]),
+            1.0
+        );
+        // A single positive.
+        assert_eq!(pr_auc(&[0.9, 0.5, 0.1], &[true, false, false]), 1.0);
+        assert_eq!(pr_auc(&[0.9, 0.5, 0.1], &[false, true, false]), 0.25);
+    }
+}
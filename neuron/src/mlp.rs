@@ -1,4 +1,22 @@
-use crate::{layer::Layer, val::Val};
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, ErrorKind},
+    iter::Peekable,
+    path::Path,
+    str::{FromStr, Lines},
+};
+
+use crate::{layer::Layer, neuron::Activation, optim::gradient_norm, val::Val};
+
+/// See [`Mlp::gradient_norms`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct GradientNorms {
+    /// Over every parameter of the model.
+    pub global: f64,
+    /// Over the parameters of each layer, input layer first.
+    pub per_layer: Vec<f64>,
+}
 
 pub struct Mlp {
     layers: Vec<Layer>,
@@ -16,8 +34,33 @@ impl Mlp {
         }
     }
 
+    /// A model made of `layers`, each taking the outputs of the one before as inputs.
+    pub(crate) fn from_layers(layers: Vec<Layer>) -> Self {
+        Self { layers }
+    }
+
+    /// Use `activation` for the last layer instead of ReLU, e.g. [`Activation::Linear`] so the
+    /// outputs can be negative.
+    pub fn with_output_activation(mut self, activation: Activation) -> Self {
+        if let Some(last) = self.layers.pop() {
+            self.layers.push(last.with_activation(activation));
+        }
+        self
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
     pub fn forward(&self, xs: &[f64]) -> Vec<Val> {
-        let mut input = xs.iter().map(|x| Val::from(*x)).collect::<Vec<_>>();
+        let input = xs.iter().map(|x| Val::from(*x)).collect::<Vec<_>>();
+        self.forward_vals(&input)
+    }
+
+    /// Like [`Mlp::forward`] but with inputs that are already part of a graph, e.g. the outputs
+    /// of another model.
+    pub fn forward_vals(&self, inputs: &[Val]) -> Vec<Val> {
+        let mut input = inputs.to_vec();
 
         for layer in &self.layers {
             input = layer.forward(&input);
@@ -25,11 +68,231 @@ impl Mlp {
         }
         input
     }
+
+    /// The index of the highest output for `xs`, ties go to the lowest index.
+    pub fn predict_class(&self, xs: &[f64]) -> usize {
+        self.top_k(xs, 1)[0]
+    }
+
+    /// The indices of the `k` highest outputs for `xs`, highest first. Ties go to the lowest
+    /// index and NaN outputs rank below everything else.
+    pub fn top_k(&self, xs: &[f64], k: usize) -> Vec<usize> {
+        let outputs: Vec<f64> = self.forward(xs).iter().map(Val::data).collect();
+
+        let mut indices: Vec<usize> = (0..outputs.len()).collect();
+        // The sort is stable, equal outputs stay in index order.
+        indices.sort_by(|&a, &b| match (outputs[a].is_nan(), outputs[b].is_nan()) {
+            (false, false) => outputs[b].partial_cmp(&outputs[a]).unwrap(),
+            (a_nan, b_nan) => a_nan.cmp(&b_nan),
+        });
+        indices.truncate(k);
+        indices
+    }
+
+    /// Apply a per-neuron max-norm constraint to every layer, see [`Neuron::apply_max_norm`].
+    ///
+    /// [`Neuron::apply_max_norm`]: crate::neuron::Neuron::apply_max_norm
+    pub fn apply_max_norm(&self, max_norm: f64) {
+        for layer in &self.layers {
+            layer.apply_max_norm(max_norm);
+        }
+    }
+
+    /// The L2 norms of the current gradients of the parameters, typically recorded after every
+    /// backward pass to spot exploding or vanishing gradients.
+    pub fn gradient_norms(&self) -> GradientNorms {
+        let per_layer: Vec<f64> = self
+            .layers
+            .iter()
+            .map(|l| gradient_norm(&l.parameters()))
+            .collect();
+
+        GradientNorms {
+            global: per_layer.iter().map(|n| n.powi(2)).sum::<f64>().sqrt(),
+            per_layer,
+        }
+    }
+
+    pub fn parameters(&self) -> Vec<Val> {
+        self.layers.iter().flat_map(|l| l.parameters()).collect()
+    }
+
+    /// The version in the header of [`Mlp::save`], changed with the format.
+    const FORMAT_VERSION: u32 = 1;
+
+    /// Write the shape, activations and parameters of the model to `path`, as text.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let num_inputs = self.layers.first().map_or(0, Layer::num_inputs);
+        let shapes = self
+            .layers
+            .iter()
+            .map(|l| (l.num_outputs(), l.activation()));
+        let mut out = write_header("mlp", Self::FORMAT_VERSION, num_inputs, shapes);
+        // The Debug form of a float parses back to the same value.
+        for p in self.parameters() {
+            out.push_str(&format!("{:?}\n", p.data()));
+        }
+        fs::write(path, out)
+    }
+
+    /// Read a model written by [`Mlp::save`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines().peekable();
+
+        let layers = read_header(&mut lines, "mlp", Self::FORMAT_VERSION)?
+            .into_iter()
+            .map(|(num_inputs, size, activation)| {
+                Layer::new(num_inputs, size).with_activation(activation)
+            })
+            .collect();
+        let mlp = Self { layers };
+
+        let params = mlp.parameters();
+        let values: Vec<f64> = lines
+            .map(|l| parse(Some(l), "parameter"))
+            .collect::<io::Result<_>>()?;
+        if values.len() != params.len() {
+            return Err(invalid("wrong number of parameters"));
+        }
+        for (p, v) in params.iter().zip(values) {
+            p.set_data(v);
+        }
+        Ok(mlp)
+    }
+
+    /// [`Mlp::parameters`] with their names, e.g. `layers.1.neurons.0.weights.3`.
+    pub fn named_parameters(&self) -> Vec<(String, Val)> {
+        self.layers
+            .iter()
+            .enumerate()
+            .flat_map(|(i, l)| {
+                l.named_parameters()
+                    .into_iter()
+                    .map(move |(name, p)| (format!("layers.{i}.{name}"), p))
+            })
+            .collect()
+    }
+}
+
+/// The first lines of the save formats of models: `{kind} {version} {num_inputs}`, then
+/// `layer {size} {activation}` for every layer.
+pub(crate) fn write_header(
+    kind: &str,
+    version: u32,
+    num_inputs: usize,
+    layers: impl IntoIterator<Item = (usize, Activation)>,
+) -> String {
+    let mut out = format!("{kind} {version} {num_inputs}\n");
+    for (size, activation) in layers {
+        out.push_str(&format!("layer {size} {activation:?}\n"));
+    }
+    out
+}
+
+/// Read the lines written by [`write_header`], and return the inputs, outputs and activation
+/// of every layer. Other kinds of models and versions are rejected.
+pub(crate) fn read_header(
+    lines: &mut Peekable<Lines<'_>>,
+    kind: &str,
+    version: u32,
+) -> io::Result<Vec<(usize, usize, Activation)>> {
+    let mut header = lines
+        .next()
+        .and_then(|l| l.strip_prefix(kind))
+        .and_then(|l| l.strip_prefix(' '))
+        .ok_or_else(|| invalid(&format!("missing {kind} header")))?
+        .split(' ');
+    let found: u32 = parse(header.next(), "version")?;
+    if found != version {
+        return Err(invalid(&format!(
+            "unsupported {kind} format version {found}"
+        )));
+    }
+    let mut num_inputs: usize = parse(header.next(), "number of inputs")?;
+
+    let mut shapes = vec![];
+    while let Some(layer) = lines.peek().and_then(|l| l.strip_prefix("layer ")) {
+        let (size, activation) = layer
+            .split_once(' ')
+            .ok_or_else(|| invalid("invalid layer"))?;
+        let size: usize = parse(Some(size), "layer size")?;
+        let activation: Activation = activation.parse().map_err(|e: String| invalid(&e))?;
+
+        shapes.push((num_inputs, size, activation));
+        num_inputs = size;
+        lines.next();
+    }
+    Ok(shapes)
+}
+
+/// A field of a saved model, an error naming `what` if it's missing or doesn't parse.
+pub(crate) fn parse<T: FromStr>(field: Option<&str>, what: &str) -> io::Result<T> {
+    field
+        .and_then(|f| f.parse().ok())
+        .ok_or_else(|| invalid(&format!("invalid {what}")))
+}
+
+pub(crate) fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// The shape of every layer. The alternate form, `{:#}`, also runs a forward pass to count the
+/// ops of the graph it builds and estimate its FLOPs.
+impl Display for Mlp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let num_inputs = self.layers.first().map_or(0, Layer::num_inputs);
+        let shape: Vec<String> = std::iter::once(num_inputs)
+            .chain(self.layers.iter().map(Layer::num_outputs))
+            .map(|n| n.to_string())
+            .collect();
+        write!(
+            f,
+            "Mlp {}, {} parameters",
+            shape.join(" -> "),
+            self.parameters().len()
+        )?;
+
+        for (i, layer) in self.layers.iter().enumerate() {
+            write!(
+                f,
+                "\n  layer {i}: {} -> {}, {:?}, {} parameters",
+                layer.num_inputs(),
+                layer.num_outputs(),
+                layer.activation(),
+                layer.parameters().len()
+            )?;
+        }
+
+        if f.alternate() {
+            let outputs = self.forward(&vec![0.0; num_inputs]);
+            let mut total = Val::from(0.0);
+            for output in outputs {
+                total.accumulate(output);
+            }
+
+            // The sum joining the outputs is not part of the model.
+            let mut counts = total.op_counts();
+            counts.remove("Σ");
+            let flops = total.flops() - self.layers.last().map_or(0, Layer::num_outputs);
+            total.free_graph();
+
+            let ops: Vec<String> = counts.iter().map(|(op, n)| format!("{op}: {n}")).collect();
+            write!(
+                f,
+                "\n  per forward pass: {} ops ({}), ~{flops} FLOPs",
+                counts.values().sum::<usize>(),
+                ops.join(", ")
+            )?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Mlp;
+    use crate::neuron::Activation;
 
     #[test]
     fn test_mlp() {
@@ -37,6 +300,73 @@ mod tests {
         let mlp = Mlp::new(3, vec![4, 4, 1]);
         let output = mlp.forward(&x);
         println!("{output:?}");
+        #[cfg(feature = "notebook")]
         output[0].visualize();
     }
+
+    #[test]
+    fn gradient_norms() {
+        let mlp = Mlp::new(2, vec![2, 1]);
+        for (i, p) in mlp.parameters().iter().enumerate() {
+            p.set_gradient(if i < 6 { 1.0 } else { 2.0 });
+        }
+
+        let norms = mlp.gradient_norms();
+        assert_eq!(norms.per_layer, vec![6f64.sqrt(), 12f64.sqrt()]);
+        assert!((norms.global - 18f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn top_k() {
+        let mlp = Mlp::new(1, vec![4]).with_output_activation(Activation::Linear);
+        let outputs = [1.0, 3.0, 1.0, f64::NAN];
+        for (neuron, output) in mlp.layers[0].parameters().chunks(2).zip(outputs) {
+            // Zero weight, the bias is the output.
+            neuron[0].set_data(0.0);
+            neuron[1].set_data(output);
+        }
+
+        assert_eq!(mlp.predict_class(&[0.0]), 1);
+        assert_eq!(mlp.top_k(&[0.0], 3), vec![1, 0, 2]);
+        assert_eq!(mlp.top_k(&[0.0], 10), vec![1, 0, 2, 3]);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mlp = Mlp::new(3, vec![4, 2]).with_output_activation(Activation::PReLU(0.1));
+        let path = std::env::temp_dir().join(format!("neuron-mlp-{}.txt", std::process::id()));
+        mlp.save(&path).unwrap();
+        let loaded = Mlp::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.to_string(), mlp.to_string());
+        let data = |m: &Mlp| m.parameters().iter().map(|p| p.data()).collect::<Vec<_>>();
+        assert_eq!(data(&loaded), data(&mlp));
+    }
+
+    #[test]
+    fn load_unknown_version() {
+        let path = std::env::temp_dir().join(format!("neuron-mlp-v2-{}.txt", std::process::id()));
+        std::fs::write(&path, "mlp 2 1\nlayer 1 Linear\n0.5\n0.0\n").unwrap();
+        let loaded = Mlp::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let error = loaded.err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "unsupported mlp format version 2");
+    }
+
+    #[test]
+    fn display() {
+        let mlp = Mlp::new(3, vec![4, 1]).with_output_activation(Activation::Linear);
+
+        assert_eq!(
+            mlp.to_string(),
+            "Mlp 3 -> 4 -> 1, 21 parameters\n  \
+             layer 0: 3 -> 4, ReLU, 16 parameters\n  \
+             layer 1: 4 -> 1, Linear, 5 parameters"
+        );
+        assert!(format!("{mlp:#}")
+            .ends_with("per forward pass: 36 ops (*: 16, +: 16, ReLU: 4), ~36 FLOPs"));
+    }
 }
@@ -1,10 +1,133 @@
+use std::str::FromStr;
+
 use rand::{thread_rng, Rng};
 
-use crate::val::Val;
+use crate::{
+    ops::dot,
+    val::{self, Val},
+};
+
+/// The non-linearity applied to the weighted sum of a neuron's inputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Activation {
+    #[default]
+    ReLU,
+    /// ReLU with negative inputs scaled by the slope instead of zeroed, so neurons can't die.
+    LeakyReLU(f64),
+    /// Leaky ReLU whose slope, starting at the given value, is a parameter of every neuron,
+    /// see [`Val::prelu`]. Applied on its own, the slope stays at its initial value.
+    PReLU(f64),
+    /// The exponential linear unit with the given `alpha`, see [`Val::elu`].
+    ELU(f64),
+    /// The Gaussian error linear unit of transformer blocks, see [`Val::gelu`].
+    GELU,
+    /// The sigmoid linear unit, or swish, see [`Val::silu`].
+    SiLU,
+    /// `x * tanh(softplus(x))`, see [`Val::mish`].
+    Mish,
+    Tanh,
+    /// Tanh clamped piecewise linearly, see [`Val::hard_tanh`].
+    HardTanh,
+    /// Outputs in (0, 1), e.g. probabilities for binary classification.
+    Sigmoid,
+    /// Sigmoid clamped piecewise linearly, see [`Val::hard_sigmoid`].
+    HardSigmoid,
+    /// No non-linearity, e.g. for the output layer of a regression model.
+    Linear,
+}
+
+impl Activation {
+    pub fn apply(&self, x: Val) -> Val {
+        match self {
+            Activation::ReLU => x.relu(),
+            Activation::LeakyReLU(alpha) | Activation::PReLU(alpha) => x.leaky_relu(*alpha),
+            Activation::ELU(alpha) => x.elu(*alpha),
+            Activation::GELU => x.gelu(),
+            Activation::SiLU => x.silu(),
+            Activation::Mish => x.mish(),
+            Activation::Tanh => x.tanh(),
+            Activation::HardTanh => x.hard_tanh(),
+            Activation::Sigmoid => x.sigmoid(),
+            Activation::HardSigmoid => x.hard_sigmoid(),
+            Activation::Linear => x,
+        }
+    }
+
+    /// [`Activation::apply`] on a plain number, without building a graph, e.g. for inference.
+    pub fn apply_f64(&self, x: f64) -> f64 {
+        match self {
+            Activation::ReLU => {
+                if x < 0.0 {
+                    0.0
+                } else {
+                    x
+                }
+            }
+            Activation::LeakyReLU(alpha) | Activation::PReLU(alpha) => {
+                if x < 0.0 {
+                    alpha * x
+                } else {
+                    x
+                }
+            }
+            Activation::ELU(alpha) => {
+                if x > 0.0 {
+                    x
+                } else {
+                    alpha * x.exp_m1()
+                }
+            }
+            Activation::GELU => val::gelu(x),
+            Activation::SiLU => x * val::sigmoid(x),
+            Activation::Mish => x * val::tanh_softplus(x),
+            Activation::Tanh => x.tanh(),
+            Activation::HardTanh => x.clamp(-1.0, 1.0),
+            Activation::Sigmoid => val::sigmoid(x),
+            Activation::HardSigmoid => (x / 6.0 + 0.5).clamp(0.0, 1.0),
+            Activation::Linear => x,
+        }
+    }
+}
+
+/// Parses the [`Debug`] form of an activation, e.g. `Tanh` or `LeakyReLU(0.1)`.
+impl FromStr for Activation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.strip_suffix(')').and_then(|s| s.split_once('(')) {
+            Some((name, arg)) => {
+                let arg = arg
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid parameter in activation '{s}'"))?;
+                (name, Some(arg))
+            }
+            None => (s, None),
+        };
+
+        match (name, arg) {
+            ("ReLU", None) => Ok(Activation::ReLU),
+            ("LeakyReLU", Some(alpha)) => Ok(Activation::LeakyReLU(alpha)),
+            ("PReLU", Some(slope)) => Ok(Activation::PReLU(slope)),
+            ("ELU", Some(alpha)) => Ok(Activation::ELU(alpha)),
+            ("GELU", None) => Ok(Activation::GELU),
+            ("SiLU", None) => Ok(Activation::SiLU),
+            ("Mish", None) => Ok(Activation::Mish),
+            ("Tanh", None) => Ok(Activation::Tanh),
+            ("HardTanh", None) => Ok(Activation::HardTanh),
+            ("Sigmoid", None) => Ok(Activation::Sigmoid),
+            ("HardSigmoid", None) => Ok(Activation::HardSigmoid),
+            ("Linear", None) => Ok(Activation::Linear),
+            _ => Err(format!("unknown activation '{s}'")),
+        }
+    }
+}
 
 pub struct Neuron {
     weights: Vec<Val>,
     bias: Val,
+    activation: Activation,
+    /// The learned slope of [`Activation::PReLU`].
+    slope: Option<Val>,
 }
 
 impl Neuron {
@@ -15,14 +138,189 @@ impl Neuron {
             .collect::<Vec<_>>();
         let bias = Val::from(rng.gen_range(-1.0..1.0)).with_label("b");
 
-        Self { weights, bias }
+        Self {
+            weights,
+            bias,
+            activation: Activation::default(),
+            slope: None,
+        }
+    }
+
+    pub fn with_activation(mut self, activation: Activation) -> Neuron {
+        self.activation = activation;
+        self.slope = match activation {
+            Activation::PReLU(slope) => Some(Val::from(slope).with_label("a")),
+            _ => None,
+        };
+        self
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.weights.len()
+    }
+
+    pub fn activation(&self) -> Activation {
+        self.activation
+    }
+
+    pub fn weights(&self) -> &[Val] {
+        &self.weights
+    }
+
+    pub fn bias(&self) -> &Val {
+        &self.bias
+    }
+
+    /// The learned slope of [`Activation::PReLU`], `None` for other activations.
+    pub fn slope(&self) -> Option<&Val> {
+        self.slope.as_ref()
     }
 
     pub fn forward(&self, inputs: &[Val]) -> Val {
-        inputs
+        let x = &self.bias + dot(inputs, &self.weights);
+        match &self.slope {
+            Some(slope) => x.prelu(slope),
+            None => self.activation.apply(x),
+        }
+    }
+
+    /// Rescale the incoming weight vector so its L2 norm does not exceed `max_norm`. The bias is
+    /// left untouched. Meant to be called after every optimizer step.
+    pub fn apply_max_norm(&self, max_norm: f64) {
+        let norm = self
+            .weights
             .iter()
-            .zip(self.weights.iter().cloned())
-            .fold(self.bias.clone(), |acc, (a, b)| acc + a * b)
-            .relu()
+            .map(|w| w.data().powi(2))
+            .sum::<f64>()
+            .sqrt();
+
+        if norm > max_norm {
+            let scale = max_norm / norm;
+            for w in &self.weights {
+                w.set_data(w.data() * scale);
+            }
+        }
+    }
+
+    /// [`Neuron::parameters`] with their names: `weights.i`, `bias` and `slope`.
+    pub fn named_parameters(&self) -> Vec<(String, Val)> {
+        let mut params: Vec<(String, Val)> = self
+            .weights
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (format!("weights.{i}"), w.clone()))
+            .collect();
+        params.push(("bias".to_string(), self.bias.clone()));
+        params.extend(self.slope.iter().map(|s| ("slope".to_string(), s.clone())));
+        params
+    }
+
+    /// The weights followed by the bias, and the slope of [`Activation::PReLU`].
+    pub fn parameters(&self) -> Vec<Val> {
+        let mut params = self.weights.clone();
+        params.push(self.bias.clone());
+        params.extend(self.slope.clone());
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Activation, Neuron};
+    use crate::val::Val;
+
+    #[test]
+    fn activations() {
+        let n = Neuron::new(1).with_activation(Activation::Linear);
+        n.weights[0].set_data(1.0);
+        n.bias.set_data(0.0);
+
+        assert_eq!(n.forward(&[Val::from(-2.0)]).data(), -2.0);
+
+        let n = n.with_activation(Activation::LeakyReLU(0.25));
+        assert_eq!(n.forward(&[Val::from(-2.0)]).data(), -0.5);
+
+        let n = n.with_activation(Activation::PReLU(0.25));
+        let y = n.forward(&[Val::from(-2.0)]);
+        assert_eq!(y.data(), -0.5);
+        y.back_prop_gradient();
+        assert_eq!(n.parameters().len(), 3);
+        assert_eq!(n.parameters()[2].gradient(), -2.0);
+
+        let n = n.with_activation(Activation::ELU(1.0));
+        assert_eq!(n.forward(&[Val::from(-2.0)]).data(), (-2.0f64).exp_m1());
+
+        let n = n.with_activation(Activation::SiLU);
+        assert!((n.forward(&[Val::from(-2.0)]).data() + 2.0 / (1.0 + 2f64.exp())).abs() < 1e-12);
+
+        let n = n.with_activation(Activation::HardTanh);
+        assert_eq!(n.forward(&[Val::from(-2.0)]).data(), -1.0);
+
+        let n = n.with_activation(Activation::HardSigmoid);
+        assert_eq!(n.forward(&[Val::from(-1.5)]).data(), 0.25);
+    }
+
+    #[test]
+    fn plain_activations() {
+        for activation in [
+            Activation::ReLU,
+            Activation::LeakyReLU(0.1),
+            Activation::PReLU(0.25),
+            Activation::ELU(1.5),
+            Activation::GELU,
+            Activation::SiLU,
+            Activation::Mish,
+            Activation::Tanh,
+            Activation::HardTanh,
+            Activation::Sigmoid,
+            Activation::HardSigmoid,
+            Activation::Linear,
+        ] {
+            for x in [-40.0, -3.5, -0.5, 0.0, 0.7, 2.0, 40.0] {
+                let expected = activation.apply(Val::from(x)).data();
+                assert_eq!(activation.apply_f64(x), expected, "{activation:?} of {x}");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_activations() {
+        for activation in [
+            Activation::ReLU,
+            Activation::LeakyReLU(0.01),
+            Activation::PReLU(0.25),
+            Activation::ELU(1.5),
+            Activation::Mish,
+            Activation::HardSigmoid,
+            Activation::Linear,
+        ] {
+            assert_eq!(format!("{activation:?}").parse(), Ok(activation));
+        }
+        assert!("LeakyReLU".parse::<Activation>().is_err());
+        assert!("Swish(1)".parse::<Activation>().is_err());
+    }
+
+    #[test]
+    fn max_norm() {
+        let n = Neuron::new(3);
+        for w in &n.weights {
+            w.set_data(3.0);
+        }
+        n.bias.set_data(5.0);
+
+        n.apply_max_norm(1.0);
+
+        let norm = n
+            .weights
+            .iter()
+            .map(|w| w.data().powi(2))
+            .sum::<f64>()
+            .sqrt();
+        assert!((norm - 1.0).abs() < 1e-12);
+        assert_eq!(n.bias.data(), 5.0);
+
+        // Weights already within the limit are not touched.
+        n.apply_max_norm(2.0);
+        assert!((n.weights[0].data() - 3.0 / 27f64.sqrt()).abs() < 1e-12);
     }
 }
@@ -0,0 +1,86 @@
+//! Rich output for evcxr notebooks, next to [`Val::visualize`].
+//!
+//! evcxr renders values whose type has an `evcxr_display` method, and anything printed between
+//! the `EVCXR_BEGIN_CONTENT` and `EVCXR_END_CONTENT` markers, as HTML.
+use crate::{
+    history::{escape, History},
+    mlp::Mlp,
+    val::Val,
+};
+
+fn display_html(html: &str) {
+    println!("EVCXR_BEGIN_CONTENT text/html\n{html}\nEVCXR_END_CONTENT");
+}
+
+fn params_table(params: &[Val]) -> String {
+    let mut out =
+        String::from("<table>\n<tr><th>#</th><th>label</th><th>data</th><th>grad</th></tr>\n");
+    for (i, p) in params.iter().enumerate() {
+        let label = p.label().unwrap_or_default();
+        out.push_str(&format!(
+            "<tr><td>{i}</td><td>{}</td><td>{:.4}</td><td>{:.4}</td></tr>\n",
+            escape(&label),
+            p.data(),
+            p.gradient()
+        ));
+    }
+    out.push_str("</table>");
+    out
+}
+
+fn mlp_table(mlp: &Mlp) -> String {
+    let mut out = String::from(
+        "<table>\n<tr><th>layer</th><th>inputs</th><th>outputs</th><th>activation</th>\
+         <th>parameters</th></tr>\n",
+    );
+    for (i, layer) in mlp.layers().iter().enumerate() {
+        out.push_str(&format!(
+            "<tr><td>{i}</td><td>{}</td><td>{}</td><td>{:?}</td><td>{}</td></tr>\n",
+            layer.num_inputs(),
+            layer.num_outputs(),
+            layer.activation(),
+            layer.parameters().len()
+        ));
+    }
+    out.push_str(&format!(
+        "<tr><th colspan=\"4\">total</th><td>{}</td></tr>\n</table>",
+        mlp.parameters().len()
+    ));
+    out
+}
+
+/// Show the label, data and gradient of every parameter in a table.
+pub fn show_table(params: &[Val]) {
+    display_html(&params_table(params));
+}
+
+/// Show a line chart of every series recorded in `history`.
+pub fn plot_history(history: &History) {
+    display_html(&history.charts_html());
+}
+
+impl Mlp {
+    /// Shown by evcxr when an [`Mlp`] is the value of a cell: a table of its layers.
+    pub fn evcxr_display(&self) {
+        display_html(&mlp_table(self));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mlp_table, params_table};
+    use crate::{mlp::Mlp, val::Val};
+
+    #[test]
+    fn tables() {
+        let params = [Val::new(1.5, "w<0>"), Val::from(-2.0)];
+        let html = params_table(&params);
+        assert!(html.contains("<td>0</td><td>w&lt;0&gt;</td><td>1.5000</td><td>0.0000</td>"));
+        assert!(html.contains("<td>1</td><td></td><td>-2.0000</td>"));
+
+        let html = mlp_table(&Mlp::new(3, vec![4, 2]));
+        assert!(html.contains("<td>0</td><td>3</td><td>4</td><td>ReLU</td><td>16</td>"));
+        assert!(html.contains("<td>1</td><td>4</td><td>2</td><td>ReLU</td><td>10</td>"));
+        assert!(html.contains("<td>26</td>"));
+    }
+}
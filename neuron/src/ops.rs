@@ -0,0 +1,242 @@
+//! Ops over vectors of nodes, built from the scalar ops of [`Val`].
+use crate::val::Val;
+
+/// Keeps the norms of zero vectors from dividing by zero, and zero probabilities out of
+/// logarithms.
+const EPS: f64 = 1e-12;
+
+/// `Σ a_i b_i`, with the products added as a balanced tree so the graph is only logarithmically
+/// deep in the length of the vectors.
+pub fn dot(a: &[Val], b: &[Val]) -> Val {
+    assert_eq!(a.len(), b.len(), "vectors must have the same length");
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// The Euclidean norm `sqrt(Σ x²)` of `xs`, e.g. of the weights of a model for a weight decay
+/// term added to the loss. Its gradient is `x / ||x||`, and 0 for the zero vector.
+pub fn l2_norm(xs: &[Val]) -> Val {
+    Val::l2_norm(xs)
+}
+
+/// The cosine of the angle between `a` and `b`, 0 if either is the zero vector.
+pub fn cosine_similarity(a: &[Val], b: &[Val]) -> Val {
+    assert_eq!(a.len(), b.len(), "vectors must have the same length");
+
+    let mut dot = Val::from(0.0);
+    let mut a_norm = Val::from(0.0);
+    let mut b_norm = Val::from(0.0);
+    for (x, y) in a.iter().zip(b) {
+        dot.accumulate(x * y);
+        a_norm.accumulate(x.powi(2));
+        b_norm.accumulate(y.powi(2));
+    }
+
+    // 1 / sqrt(max(|a|² |b|², EPS)), without a square root of zero to backpropagate through.
+    dot * Val::from(1.0).div_eps(&(a_norm * b_norm), EPS).sqrt()
+}
+
+/// The probabilities `e^z / Σ e^z` of the classes scored by `logits`, computed from
+/// `z - max` so large logits can't overflow.
+pub fn softmax(logits: &[Val]) -> Vec<Val> {
+    // Shifting every logit by the same constant doesn't change the result, so the max needs no
+    // gradient.
+    let max = logits
+        .iter()
+        .map(Val::data)
+        .fold(f64::NEG_INFINITY, f64::max);
+
+    let exps: Vec<Val> = logits.iter().map(|z| (z - max).exp()).collect();
+    let inv_total = exps.iter().cloned().sum::<Val>().recip();
+    exps.iter().map(|e| e * &inv_total).collect()
+}
+
+/// `ln Σ e^z`, computed as `max + ln Σ e^(z - max)` so large logits can't overflow, e.g. the
+/// log partition function of a CRF or the normalizer of a cross-entropy. Its gradient is the
+/// [`softmax`] of `logits`.
+pub fn logsumexp(logits: &[Val]) -> Val {
+    assert!(!logits.is_empty(), "logsumexp needs at least one logit");
+
+    // Shifting every logit by the same constant doesn't change the result, so the max needs no
+    // gradient.
+    let max = logits
+        .iter()
+        .map(Val::data)
+        .fold(f64::NEG_INFINITY, f64::max);
+
+    let mut total = Val::from(0.0);
+    for z in logits {
+        total.accumulate((z - max).exp());
+    }
+    total.ln() + max
+}
+
+/// The logarithms of the [`softmax`] of `logits`, computed as `z - logsumexp(z)` so large
+/// logits can't overflow and tiny probabilities don't round to a log of zero, unlike taking
+/// the `ln` of the softmax.
+pub fn log_softmax(logits: &[Val]) -> Vec<Val> {
+    let log_total = logsumexp(logits);
+    logits.iter().map(|z| z - &log_total).collect()
+}
+
+/// The index of the largest of `xs`, the first one on ties.
+pub(crate) fn argmax(xs: &[f64]) -> usize {
+    let mut best = 0;
+    for (i, x) in xs.iter().enumerate() {
+        if *x > xs[best] {
+            best = i;
+        }
+    }
+    best
+}
+
+/// [`log_softmax`] of plain numbers, e.g. the logits of a frozen model when decoding.
+pub(crate) fn log_softmax_f64(logits: &[f64]) -> Vec<f64> {
+    let max = logits.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let log_total = logits.iter().map(|z| (z - max).exp()).sum::<f64>().ln() + max;
+    logits.iter().map(|z| z - log_total).collect()
+}
+
+/// The Shannon entropy `-Σ p ln p` of the distribution `probs`, in nats. Zero probabilities
+/// contribute nothing.
+pub fn entropy(probs: &[Val]) -> Val {
+    let mut total = Val::from(0.0);
+    for p in probs {
+        total.accumulate(p * (p + EPS).ln());
+    }
+    -total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cosine_similarity, dot, entropy, l2_norm, log_softmax, logsumexp, softmax};
+    use crate::{testing::numerical_gradient, val::Val};
+
+    fn vals(xs: &[f64]) -> Vec<Val> {
+        xs.iter().map(|x| Val::from(*x)).collect()
+    }
+
+    #[test]
+    fn cosine() {
+        let a = vals(&[1.0, 0.0]);
+        assert!((cosine_similarity(&a, &vals(&[2.0, 0.0])).data() - 1.0).abs() < 1e-9);
+        assert!(cosine_similarity(&a, &vals(&[0.0, 3.0])).data().abs() < 1e-9);
+        assert!((cosine_similarity(&a, &vals(&[-1.0, 0.0])).data() + 1.0).abs() < 1e-9);
+        let zeros = vals(&[0.0, 0.0]);
+        let cos = cosine_similarity(&a, &zeros);
+        cos.back_prop_gradient();
+        assert_eq!(cos.data(), 0.0);
+        assert!(zeros.iter().chain(&a).all(|x| x.gradient().is_finite()));
+
+        let a = vals(&[0.3, -1.2, 2.0]);
+        let b = vals(&[1.5, 0.4, -0.7]);
+        let cos = cosine_similarity(&a, &b);
+        cos.back_prop_gradient();
+        for x in a.iter().chain(&b) {
+            let expected = numerical_gradient(&cos, x, 1e-6);
+            assert!((x.gradient() - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn dot_product() {
+        let a = vals(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        let b = vals(&[1.0, 0.0, -1.0, 0.5, 2.0, 0.0, 1.0, -2.0]);
+        let y = dot(&a, &b);
+        assert_eq!(y.data(), 1.0 - 3.0 + 2.0 + 10.0 + 7.0 - 16.0);
+        // A product, then three levels of sums.
+        assert_eq!(y.depth(), 4);
+
+        y.back_prop_gradient();
+        assert_eq!(a[4].gradient(), 2.0);
+        assert_eq!(b[4].gradient(), 5.0);
+    }
+
+    #[test]
+    fn norms() {
+        let xs = vals(&[3.0, -4.0]);
+        let norm = l2_norm(&xs);
+        assert_eq!(norm.data(), 5.0);
+
+        // A weight decay term over the same weight twice.
+        let penalty = l2_norm(&[xs[0].clone(), xs[1].clone(), xs[0].clone()]) * 2.0;
+        penalty.back_prop_gradient();
+        for x in &xs {
+            let expected = numerical_gradient(&penalty, x, 1e-6);
+            assert!((x.gradient() - expected).abs() < 1e-6);
+        }
+
+        let zeros = vals(&[0.0, 0.0]);
+        let norm = l2_norm(&zeros);
+        norm.back_prop_gradient();
+        assert_eq!((norm.data(), zeros[0].gradient()), (0.0, 0.0));
+        assert_eq!(l2_norm(&[]).data(), 0.0);
+    }
+
+    #[test]
+    fn entropies() {
+        let uniform = vals(&[0.25, 0.25, 0.25, 0.25]);
+        assert!((entropy(&uniform).data() - 4.0f64.ln()).abs() < 1e-9);
+        assert!(entropy(&vals(&[1.0, 0.0])).data().abs() < 1e-9);
+
+        let probs = vals(&[0.2, 0.8]);
+        let h = entropy(&probs);
+        h.back_prop_gradient();
+        // d/dp -p ln p = -ln p - 1
+        assert!((probs[0].gradient() + 0.2f64.ln() + 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn log_softmax_is_stable() {
+        let logits = vals(&[1000.0, 1000.0, 0.0]);
+        let log_probs: Vec<f64> = log_softmax(&logits).iter().map(Val::data).collect();
+        assert!((log_probs[0] - 0.5f64.ln()).abs() < 1e-9);
+        assert!(log_probs[2] < -999.0);
+
+        let logits = vals(&[0.5, -1.0, 2.0]);
+        let log_probs = log_softmax(&logits);
+        let y = &log_probs[1] * 3.0 - &log_probs[2];
+        y.back_prop_gradient();
+        for z in &logits {
+            let expected = numerical_gradient(&y, z, 1e-6);
+            assert!((z.gradient() - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn logsumexp_is_stable() {
+        let lse = logsumexp(&vals(&[1000.0, 1000.0]));
+        assert!((lse.data() - 1000.0 - 2.0f64.ln()).abs() < 1e-9);
+        assert_eq!(logsumexp(&vals(&[-3.0])).data(), -3.0);
+
+        let logits = vals(&[0.5, -1.0, 2.0]);
+        let lse = logsumexp(&logits);
+        lse.back_prop_gradient();
+        let probs = softmax(&logits);
+        for (z, p) in logits.iter().zip(&probs) {
+            let expected = numerical_gradient(&lse, z, 1e-6);
+            assert!((z.gradient() - expected).abs() < 1e-6);
+            assert!((z.gradient() - p.data()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn softmax_is_stable() {
+        let probs: Vec<f64> = softmax(&vals(&[1000.0, 1000.0, 0.0]))
+            .iter()
+            .map(Val::data)
+            .collect();
+        assert_eq!(probs[..2], [0.5, 0.5]);
+        assert!(probs[2] < 1e-300);
+
+        let logits = vals(&[0.5, -1.0, 2.0]);
+        let probs = softmax(&logits);
+        assert!((probs.iter().map(Val::data).sum::<f64>() - 1.0).abs() < 1e-12);
+
+        let y = &probs[0] * 2.0 + &probs[2];
+        y.back_prop_gradient();
+        for z in &logits {
+            let expected = numerical_gradient(&y, z, 1e-6);
+            assert!((z.gradient() - expected).abs() < 1e-6);
+        }
+    }
+}
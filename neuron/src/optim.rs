@@ -0,0 +1,350 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{schedule::Schedule, val::Val};
+
+/// Called with every parameter, its gradient and the update about to be added to it, which it
+/// can change, see [`Sgd::with_pre_step_hook`].
+type PreStepHook = Box<dyn FnMut(&Val, f64, &mut f64)>;
+
+/// Called with every parameter, its gradient and the update added to it, see
+/// [`Sgd::with_post_step_hook`].
+type PostStepHook = Box<dyn FnMut(&Val, f64, f64)>;
+
+/// Plain stochastic gradient descent over a set of parameters.
+pub struct Sgd {
+    params: Vec<Val>,
+    learning_rate: f64,
+    steps: usize,
+    skip_non_finite: bool,
+    skipped_steps: usize,
+    gradient_noise: Option<GradientNoise>,
+    schedule: Option<Box<dyn Schedule>>,
+    constraints: Vec<(Vec<Val>, Constraint)>,
+    pre_step_hooks: Vec<PreStepHook>,
+    post_step_hooks: Vec<PostStepHook>,
+}
+
+impl Sgd {
+    pub fn new(params: Vec<Val>, learning_rate: f64) -> Self {
+        Self {
+            params,
+            learning_rate,
+            steps: 0,
+            skip_non_finite: false,
+            skipped_steps: 0,
+            gradient_noise: None,
+            schedule: None,
+            constraints: vec![],
+            pre_step_hooks: vec![],
+            post_step_hooks: vec![],
+        }
+    }
+
+    /// Take the learning rate of every step from `schedule` instead of the fixed one.
+    pub fn with_schedule(mut self, schedule: Box<dyn Schedule>) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    /// The learning rate used by the next step.
+    pub fn learning_rate(&self) -> f64 {
+        match &self.schedule {
+            Some(schedule) => schedule.learning_rate(self.steps),
+            None => self.learning_rate,
+        }
+    }
+
+    /// Skip the steps where a gradient is infinite or NaN instead of writing it into the
+    /// parameters. Skipped steps don't count in [`Sgd::steps`].
+    pub fn with_skip_non_finite(mut self) -> Self {
+        self.skip_non_finite = true;
+        self
+    }
+
+    /// Add annealed Gaussian noise to the gradients before every update.
+    pub fn with_gradient_noise(mut self, noise: GradientNoise) -> Self {
+        self.gradient_noise = Some(noise);
+        self
+    }
+
+    /// Project `params`, e.g. the weights of a layer, back onto `constraint` after every
+    /// update. The params don't have to be among the ones optimized.
+    pub fn with_constraint(mut self, params: Vec<Val>, constraint: Constraint) -> Self {
+        if let Constraint::Bounds(lo, hi) = constraint {
+            assert!(lo <= hi, "invalid bounds {lo} to {hi}");
+        }
+        self.constraints.push((params, constraint));
+        self
+    }
+
+    /// Call `hook` before updating every parameter, with the parameter, its gradient and the
+    /// update `-learning_rate * gradient`, which the hook can change, e.g. to clip it. Hooks run
+    /// in the order they were added.
+    pub fn with_pre_step_hook(mut self, hook: impl FnMut(&Val, f64, &mut f64) + 'static) -> Self {
+        self.pre_step_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Call `hook` after updating every parameter, before the constraints are applied, with the
+    /// parameter, its gradient and the update added to it, e.g. to log update to weight ratios.
+    pub fn with_post_step_hook(mut self, hook: impl FnMut(&Val, f64, f64) + 'static) -> Self {
+        self.post_step_hooks.push(Box::new(hook));
+        self
+    }
+
+    pub fn zero_grad(&self) {
+        for p in &self.params {
+            p.reset_gradient();
+        }
+    }
+
+    pub fn step(&mut self) {
+        if self.skip_non_finite && self.params.iter().any(|p| !p.gradient().is_finite()) {
+            self.skipped_steps += 1;
+            return;
+        }
+
+        if let Some(noise) = &mut self.gradient_noise {
+            noise.apply(&self.params, self.steps);
+        }
+
+        let learning_rate = self.learning_rate();
+        for p in &self.params {
+            let gradient = p.gradient();
+            let mut update = -learning_rate * gradient;
+            for hook in &mut self.pre_step_hooks {
+                hook(p, gradient, &mut update);
+            }
+
+            p.set_data(p.data() + update);
+            for hook in &mut self.post_step_hooks {
+                hook(p, gradient, update);
+            }
+        }
+        for (params, constraint) in &self.constraints {
+            constraint.apply(params);
+        }
+        self.steps += 1;
+    }
+
+    /// Number of updates applied so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Number of steps skipped because of non-finite gradients, see
+    /// [`Sgd::with_skip_non_finite`].
+    pub fn skipped_steps(&self) -> usize {
+        self.skipped_steps
+    }
+}
+
+/// The L2 norm of the gradients of `params`.
+pub fn gradient_norm(params: &[Val]) -> f64 {
+    params
+        .iter()
+        .map(|p| p.gradient().powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// A set of values parameters are kept in, see [`Sgd::with_constraint`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Constraint {
+    /// E.g. to make a model monotone in its inputs, with the weights of every layer
+    /// non-negative and monotone activations.
+    NonNegative,
+    /// Between the two bounds, inclusive. The lower one can't be above the upper one, and
+    /// neither can be NaN.
+    Bounds(f64, f64),
+}
+
+impl Constraint {
+    /// Move every one of `params` to the closest value satisfying the constraint.
+    pub fn apply(&self, params: &[Val]) {
+        let (lo, hi) = match *self {
+            Constraint::NonNegative => (0.0, f64::INFINITY),
+            Constraint::Bounds(lo, hi) => (lo, hi),
+        };
+        for p in params {
+            p.set_data(p.data().clamp(lo, hi));
+        }
+    }
+}
+
+/// Gradient noise as described in "Adding Gradient Noise Improves Learning for Very Deep
+/// Networks" (Neelakantan et al.).
+///
+/// At step `t` every gradient gets noise drawn from `N(0, eta / (1 + t)^gamma)`.
+#[derive(Clone, Debug)]
+pub struct GradientNoise {
+    eta: f64,
+    gamma: f64,
+    rng: StdRng,
+}
+
+impl GradientNoise {
+    /// Noise drawn by an RNG seeded with `seed`, so training with it can be reproduced.
+    pub fn new(eta: f64, gamma: f64, seed: u64) -> Self {
+        Self {
+            eta,
+            gamma,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn variance(&self, step: usize) -> f64 {
+        self.eta / (1.0 + step as f64).powf(self.gamma)
+    }
+
+    pub fn apply(&mut self, params: &[Val], step: usize) {
+        let std_dev = self.variance(step).sqrt();
+        for p in params {
+            p.set_gradient(p.gradient() + std_dev * standard_normal(&mut self.rng));
+        }
+    }
+}
+
+impl Default for GradientNoise {
+    /// The values recommended in the paper, with a seed of 0.
+    fn default() -> Self {
+        Self::new(0.3, 0.55, 0)
+    }
+}
+
+/// Sample from `N(0, 1)` using the Box-Muller transform.
+pub(crate) fn standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    // `gen` samples from [0, 1), flip it so that the log never sees a zero.
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::{gradient_norm, Constraint, GradientNoise, Sgd};
+    use crate::{schedule::Warmup, val::Val};
+
+    #[test]
+    fn step_hooks() {
+        let a = Val::new(2.0, "a");
+        let b = Val::new(-3.0, "b");
+        (&a * b.clone()).back_prop_gradient();
+
+        let ratios = Rc::new(RefCell::new(vec![]));
+        let logged = ratios.clone();
+        let mut sgd = Sgd::new(vec![a.clone(), b.clone()], 0.1)
+            .with_pre_step_hook(|_, _, update| *update = update.clamp(-0.25, 0.25))
+            .with_post_step_hook(move |p, _, update| {
+                logged.borrow_mut().push(update / p.data());
+            });
+        sgd.step();
+
+        // The update of a, 0.3, is clipped, the one of b, -0.2, isn't.
+        assert_eq!(a.data(), 2.25);
+        assert_eq!(b.data(), -3.2);
+        assert_eq!(*ratios.borrow(), [0.25 / 2.25, -0.2 / -3.2]);
+    }
+
+    #[test]
+    fn sgd_step() {
+        let a = Val::new(2.0, "a");
+        let b = Val::new(-3.0, "b");
+        let l = &a * b.clone();
+        l.back_prop_gradient();
+
+        let mut sgd = Sgd::new(vec![a.clone(), b.clone()], 0.1);
+        sgd.step();
+
+        assert_eq!(a.data(), 2.0 - 0.1 * -3.0);
+        assert_eq!(b.data(), -3.0 - 0.1 * 2.0);
+        assert_eq!(sgd.steps(), 1);
+
+        sgd.zero_grad();
+        assert_eq!(a.gradient(), 0.0);
+    }
+
+    #[test]
+    fn sgd_schedule() {
+        let a = Val::new(1.0, "a");
+        let mut sgd =
+            Sgd::new(vec![a.clone()], 1.0).with_schedule(Box::new(Warmup::new(0.0, 0.5, 2)));
+
+        assert_eq!(sgd.learning_rate(), 0.0);
+        a.set_gradient(1.0);
+        sgd.step();
+        assert_eq!(a.data(), 1.0);
+        assert_eq!(sgd.learning_rate(), 0.25);
+        sgd.step();
+        assert_eq!(a.data(), 0.75);
+    }
+
+    #[test]
+    fn gradient_norm_of_params() {
+        let a = Val::new(1.0, "a");
+        let b = Val::new(1.0, "b");
+        a.set_gradient(3.0);
+        b.set_gradient(-4.0);
+
+        assert_eq!(gradient_norm(&[a, b]), 5.0);
+    }
+
+    #[test]
+    fn gradient_noise_anneals() {
+        let noise = GradientNoise::default();
+        assert_eq!(noise.variance(0), 0.3);
+        assert!(noise.variance(100) < noise.variance(10));
+    }
+
+    #[test]
+    fn gradient_noise_perturbs() {
+        let noisy_gradient = |seed| {
+            let a = Val::new(1.0, "a");
+            GradientNoise::new(1.0, 0.55, seed).apply(std::slice::from_ref(&a), 0);
+            a.gradient()
+        };
+        assert_ne!(noisy_gradient(3), 0.0);
+        assert_eq!(noisy_gradient(3), noisy_gradient(3));
+        assert_ne!(noisy_gradient(3), noisy_gradient(4));
+    }
+
+    #[test]
+    fn constraints() {
+        let a = Val::new(0.5, "a");
+        let b = Val::new(0.5, "b");
+        let c = Val::new(0.5, "c");
+        let mut sgd = Sgd::new(vec![a.clone(), b.clone(), c.clone()], 1.0)
+            .with_constraint(vec![a.clone()], Constraint::NonNegative)
+            .with_constraint(vec![b.clone()], Constraint::Bounds(-0.25, 0.25));
+
+        for p in [&a, &b, &c] {
+            p.set_gradient(1.0);
+        }
+        sgd.step();
+
+        assert_eq!((a.data(), b.data(), c.data()), (0.0, -0.25, -0.5));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid bounds 1 to NaN")]
+    fn invalid_bounds() {
+        let a = Val::new(0.5, "a");
+        let _ = Sgd::new(vec![a.clone()], 1.0)
+            .with_constraint(vec![a], Constraint::Bounds(1.0, f64::NAN));
+    }
+
+    #[test]
+    fn skip_non_finite() {
+        let w = Val::new(1.0, "w");
+        let mut sgd = Sgd::new(vec![w.clone()], 0.5).with_skip_non_finite();
+
+        w.set_gradient(2.0);
+        sgd.step();
+        w.set_gradient(f64::INFINITY);
+        sgd.step();
+        assert_eq!(w.data(), 0.0);
+        assert_eq!((sgd.steps(), sgd.skipped_steps()), (1, 1));
+    }
+}
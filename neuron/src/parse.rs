@@ -0,0 +1,358 @@
+//! Build graphs from expressions like `a * b + relu(c)`.
+//!
+//! The grammar supports numbers, variables bound by the caller, `+`, `-`, `*`, `/`, `^` (right
+//! associative), unary minus, parentheses and the functions `relu(x)`, `gelu(x)`, `silu(x)`,
+//! `mish(x)`, `tanh(x)`, `hard_tanh(x)`, `sigmoid(x)`, `hard_sigmoid(x)`, `exp(x)`, `ln(x)`,
+//! `abs(x)`, `sign(x)`, `sqrt(x)`, `recip(x)`, `sin(x)`, `cos(x)`, `pow(x, y)`, `log(x, base)`,
+//! `max(x, y)` and `min(x, y)`.
+use std::{collections::HashMap, fmt::Display, iter::Peekable, str::CharIndices};
+
+use crate::val::Val;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Byte offset in the expression.
+    pub position: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+    End,
+}
+
+fn tokenize(expr: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars: Peekable<CharIndices> = expr.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            let mut prev = c;
+            while let Some(&(i, c)) = chars.peek() {
+                let exponent_sign = (c == '-' || c == '+') && (prev == 'e' || prev == 'E');
+                if c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign {
+                    end = i + c.len_utf8();
+                    prev = c;
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+
+            let text = &expr[start..end];
+            let number = text.parse().map_err(|_| ParseError {
+                position: start,
+                message: format!("invalid number '{text}'"),
+            })?;
+            tokens.push((start, Token::Number(number)));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push((start, Token::Ident(expr[start..end].to_string())));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push((start, Token::Symbol(c)));
+            chars.next();
+        } else {
+            return Err(ParseError {
+                position: start,
+                message: format!("unexpected character '{c}'"),
+            });
+        }
+    }
+
+    tokens.push((expr.len(), Token::End));
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    bindings: &'a HashMap<&'a str, Val>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].1
+    }
+
+    fn position(&self) -> usize {
+        self.tokens[self.next].0
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.next].1.clone();
+        if token != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, ParseError> {
+        Err(ParseError {
+            position: self.position(),
+            message,
+        })
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ParseError> {
+        if *self.peek() == Token::Symbol(symbol) {
+            self.advance();
+            Ok(())
+        } else {
+            self.error(format!("expected '{symbol}'"))
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Val, ParseError> {
+        let mut val = self.term()?;
+        loop {
+            match self.peek() {
+                Token::Symbol('+') => {
+                    self.advance();
+                    val += self.term()?;
+                }
+                Token::Symbol('-') => {
+                    self.advance();
+                    val -= self.term()?;
+                }
+                _ => return Ok(val),
+            }
+        }
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Val, ParseError> {
+        let mut val = self.unary()?;
+        loop {
+            match self.peek() {
+                Token::Symbol('*') => {
+                    self.advance();
+                    val *= self.unary()?;
+                }
+                Token::Symbol('/') => {
+                    self.advance();
+                    val /= self.unary()?;
+                }
+                _ => return Ok(val),
+            }
+        }
+    }
+
+    // unary := '-' unary | power
+    fn unary(&mut self) -> Result<Val, ParseError> {
+        if *self.peek() == Token::Symbol('-') {
+            self.advance();
+            Ok(-self.unary()?)
+        } else {
+            self.power()
+        }
+    }
+
+    // power := atom ('^' unary)?
+    fn power(&mut self) -> Result<Val, ParseError> {
+        let base = self.atom()?;
+        if *self.peek() == Token::Symbol('^') {
+            self.advance();
+            let exponent = self.unary()?;
+            Ok(base.pow(&exponent))
+        } else {
+            Ok(base)
+        }
+    }
+
+    // atom := number | name | name '(' expr (',' expr)* ')' | '(' expr ')'
+    fn atom(&mut self) -> Result<Val, ParseError> {
+        let position = self.position();
+        match self.advance() {
+            Token::Number(n) => Ok(Val::from(n)),
+            Token::Symbol('(') => {
+                let val = self.expr()?;
+                self.expect(')')?;
+                Ok(val)
+            }
+            Token::Ident(name) if *self.peek() == Token::Symbol('(') => {
+                self.advance();
+                let mut args = vec![self.expr()?];
+                while *self.peek() == Token::Symbol(',') {
+                    self.advance();
+                    args.push(self.expr()?);
+                }
+                self.expect(')')?;
+                call(&name, args).map_err(|message| ParseError { position, message })
+            }
+            Token::Ident(name) => match self.bindings.get(name.as_str()) {
+                Some(val) => Ok(val.clone()),
+                None => Err(ParseError {
+                    position,
+                    message: format!("unbound variable '{name}'"),
+                }),
+            },
+            Token::End => Err(ParseError {
+                position,
+                message: "unexpected end of expression".to_string(),
+            }),
+            Token::Symbol(c) => Err(ParseError {
+                position,
+                message: format!("unexpected '{c}'"),
+            }),
+        }
+    }
+}
+
+fn call(name: &str, mut args: Vec<Val>) -> Result<Val, String> {
+    let arity = match name {
+        "relu" | "tanh" | "sigmoid" | "exp" | "ln" | "abs" | "sqrt" | "gelu" | "silu" | "mish"
+        | "hard_tanh" | "hard_sigmoid" | "sign" | "recip" | "sin" | "cos" => 1,
+        "pow" | "log" | "max" | "min" => 2,
+        _ => return Err(format!("unknown function '{name}'")),
+    };
+    if args.len() != arity {
+        return Err(format!(
+            "{name} takes {arity} argument(s), got {}",
+            args.len()
+        ));
+    }
+
+    let x = args.remove(0);
+    Ok(match name {
+        "relu" => x.relu(),
+        "tanh" => x.tanh(),
+        "hard_tanh" => x.hard_tanh(),
+        "sigmoid" => x.sigmoid(),
+        "hard_sigmoid" => x.hard_sigmoid(),
+        "exp" => x.exp(),
+        "ln" => x.ln(),
+        "abs" => x.abs(),
+        "sign" => x.sign(),
+        "sqrt" => x.sqrt(),
+        "gelu" => x.gelu(),
+        "silu" => x.silu(),
+        "mish" => x.mish(),
+        "recip" => x.recip(),
+        "sin" => x.sin(),
+        "cos" => x.cos(),
+        // The base is a node too, so it can't use Val::log.
+        "log" => x.ln() * args[0].ln().recip(),
+        "pow" => x.pow(&args[0]),
+        "max" => x.max(&args[0]),
+        "min" => x.min(&args[0]),
+        _ => unreachable!(),
+    })
+}
+
+impl Val {
+    /// Build the graph of `expr`, with its variables taken from `bindings`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use neuron::val::Val;
+    ///
+    /// let bindings = HashMap::from([
+    ///     ("a", Val::new(2.0, "a")),
+    ///     ("b", Val::new(-3.0, "b")),
+    ///     ("c", Val::new(10.0, "c")),
+    /// ]);
+    /// let l = Val::parse("a * b + relu(c)", &bindings).unwrap();
+    /// assert_eq!(l.data(), 4.0);
+    /// ```
+    pub fn parse(expr: &str, bindings: &HashMap<&str, Val>) -> Result<Val, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(expr)?,
+            next: 0,
+            bindings,
+        };
+
+        let val = parser.expr()?;
+        match parser.peek() {
+            Token::End => Ok(val),
+            _ => parser.error("unexpected trailing input".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::ParseError;
+    use crate::val::Val;
+
+    fn eval(expr: &str) -> f64 {
+        let bindings = HashMap::from([("x", Val::new(3.0, "x")), ("y", Val::new(-2.0, "y"))]);
+        Val::parse(expr, &bindings).unwrap().data()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(eval("-x ^ 2"), -9.0);
+        assert_eq!(eval("x - y - 1"), 4.0);
+        assert_eq!(eval("x / 2 * 4"), 6.0);
+        assert_eq!(eval("relu(y) + pow(x, 2) + 1.5e1"), 24.0);
+        assert_eq!(eval("tanh(0) + exp(0) + ln(1) + sigmoid(0) + abs(y)"), 3.5);
+        assert!((eval("log(8, 2)") - 3.0).abs() < 1e-12);
+        assert_eq!(eval("max(x, y) - min(x, 2 * y)"), 7.0);
+        assert_eq!(eval("sqrt(x * x + 16)"), 5.0);
+        assert_eq!(eval("gelu(0) + silu(0) + mish(0)"), 0.0);
+    }
+
+    #[test]
+    fn gradients() {
+        let x = Val::new(3.0, "x");
+        let y = Val::new(-2.0, "y");
+        let bindings = HashMap::from([("x", x.clone()), ("y", y.clone())]);
+
+        Val::parse("x * y + relu(x)", &bindings)
+            .unwrap()
+            .back_prop_gradient();
+
+        assert_eq!(x.gradient(), -1.0);
+        assert_eq!(y.gradient(), 3.0);
+    }
+
+    #[test]
+    fn errors() {
+        let bindings = HashMap::new();
+        let error = |expr| Val::parse(expr, &bindings).unwrap_err();
+
+        assert_eq!(
+            error("1 + z"),
+            ParseError {
+                position: 4,
+                message: "unbound variable 'z'".to_string()
+            }
+        );
+        assert_eq!(error("(1 + 2").position, 6);
+        assert_eq!(error("1 2").message, "unexpected trailing input");
+        assert_eq!(
+            error("relu(1, 2)").message,
+            "relu takes 1 argument(s), got 2"
+        );
+        assert_eq!(error("tan(1)").message, "unknown function 'tan'");
+        assert_eq!(error("1 $ 2").message, "unexpected character '$'");
+    }
+}
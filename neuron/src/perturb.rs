@@ -0,0 +1,98 @@
+//! Temporary Gaussian noise on the parameters of a model, for evolution strategies and
+//! parameter-space exploration.
+//!
+//! ```
+//! use neuron::{mlp::Mlp, perturb::perturb};
+//!
+//! let mlp = Mlp::new(2, vec![3, 1]);
+//! let before = mlp.forward(&[1.0, -1.0])[0].data();
+//! {
+//!     let _noise = perturb(mlp.named_parameters(), 0.1, 42);
+//!     // Evaluate the perturbed model here.
+//! }
+//! assert_eq!(mlp.forward(&[1.0, -1.0])[0].data(), before);
+//! ```
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{optim::standard_normal, val::Val};
+
+/// Noise added to a set of parameters by [`perturb`]. The parameters get their original
+/// values back when it is dropped.
+pub struct Perturbation {
+    // The name, parameter, original value and noise of every perturbed parameter.
+    params: Vec<(String, Val, f64, f64)>,
+}
+
+/// Add noise drawn from `N(0, std_dev²)` to every one of the `named` parameters, e.g. the
+/// [`Mlp::named_parameters`] of a model, until the returned [`Perturbation`] is dropped. The
+/// noise only depends on `seed` and the order of the parameters.
+///
+/// [`Mlp::named_parameters`]: crate::mlp::Mlp::named_parameters
+pub fn perturb(named: Vec<(String, Val)>, std_dev: f64, seed: u64) -> Perturbation {
+    let mut rng = StdRng::seed_from_u64(seed);
+    Perturbation {
+        params: named
+            .into_iter()
+            .map(|(name, p)| {
+                let original = p.data();
+                let noise = std_dev * standard_normal(&mut rng);
+                p.set_data(original + noise);
+                (name, p, original, noise)
+            })
+            .collect(),
+    }
+}
+
+impl Perturbation {
+    /// The noise added to every parameter, by name, e.g. to weigh it by the return of the
+    /// perturbed model in an evolution strategies update.
+    pub fn noise(&self) -> Vec<(&str, f64)> {
+        self.params
+            .iter()
+            .map(|(name, _, _, noise)| (name.as_str(), *noise))
+            .collect()
+    }
+
+    /// Give the parameters their original values back, the same as dropping this.
+    pub fn restore(self) {}
+}
+
+impl Drop for Perturbation {
+    fn drop(&mut self) {
+        for (_, p, original, _) in &self.params {
+            p.set_data(*original);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::perturb;
+    use crate::{mlp::Mlp, val::Val};
+
+    #[test]
+    fn perturb_and_restore() {
+        let mlp = Mlp::new(2, vec![2, 1]);
+        let named = mlp.named_parameters();
+        assert_eq!(named[0].0, "layers.0.neurons.0.weights.0");
+        assert_eq!(named[2].0, "layers.0.neurons.0.bias");
+        assert_eq!(named.len(), mlp.parameters().len());
+
+        let original: Vec<f64> = mlp.parameters().iter().map(Val::data).collect();
+        let noise = perturb(mlp.named_parameters(), 0.5, 7);
+        let perturbed: Vec<f64> = mlp.parameters().iter().map(Val::data).collect();
+        for ((o, p), (_, n)) in original.iter().zip(&perturbed).zip(noise.noise()) {
+            assert_eq!(*p, o + n);
+        }
+        assert_ne!(original, perturbed);
+        noise.restore();
+
+        let restored: Vec<f64> = mlp.parameters().iter().map(Val::data).collect();
+        assert_eq!(restored, original);
+
+        let again = perturb(mlp.named_parameters(), 0.5, 7);
+        let repeated: Vec<f64> = mlp.parameters().iter().map(Val::data).collect();
+        assert_eq!(repeated, perturbed);
+        drop(again);
+    }
+}
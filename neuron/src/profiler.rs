@@ -0,0 +1,198 @@
+//! An opt-in, per-thread profiler for the operations of the computation graph.
+//!
+//! ```
+//! use neuron::{profiler, val::Val};
+//!
+//! profiler::enable();
+//! let a = Val::new(2.0, "a");
+//! let b = Val::new(-3.0, "b");
+//! (a * b).relu().back_prop_gradient();
+//! profiler::print_summary();
+//! ```
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+thread_local! {
+    static PROFILE: RefCell<Option<Profile>> = const { RefCell::new(None) };
+}
+
+/// Counters recorded for a single op type.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpStats {
+    /// Number of nodes created with this op.
+    pub count: usize,
+    /// Time spent building these nodes.
+    pub forward: Duration,
+    /// Time spent in the backward functions of these nodes.
+    pub backward: Duration,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    ops: BTreeMap<String, OpStats>,
+    backward_passes: usize,
+    backward_total: Duration,
+}
+
+impl Profile {
+    pub fn ops(&self) -> &BTreeMap<String, OpStats> {
+        &self.ops
+    }
+
+    pub fn backward_passes(&self) -> usize {
+        self.backward_passes
+    }
+
+    /// Wall time of all the backward passes, including graph traversal.
+    pub fn backward_total(&self) -> Duration {
+        self.backward_total
+    }
+
+    /// Time spent in backward passes outside of the op backward functions, i.e. walking the
+    /// graph.
+    pub fn backward_bookkeeping(&self) -> Duration {
+        let in_ops: Duration = self.ops.values().map(|s| s.backward).sum();
+        self.backward_total.saturating_sub(in_ops)
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:>10} {:>14} {:>14}",
+            "op", "count", "forward (µs)", "backward (µs)"
+        )?;
+        for (op, stats) in &self.ops {
+            writeln!(
+                f,
+                "{:<12} {:>10} {:>14} {:>14}",
+                op,
+                stats.count,
+                stats.forward.as_micros(),
+                stats.backward.as_micros()
+            )?;
+        }
+        write!(
+            f,
+            "{} backward pass(es): {}µs total, {}µs graph bookkeeping",
+            self.backward_passes,
+            self.backward_total.as_micros(),
+            self.backward_bookkeeping().as_micros()
+        )
+    }
+}
+
+/// Start recording, discarding anything recorded before.
+pub fn enable() {
+    PROFILE.with(|p| *p.borrow_mut() = Some(Profile::default()));
+}
+
+/// Stop recording and return what was recorded.
+pub fn disable() -> Option<Profile> {
+    PROFILE.with(|p| p.borrow_mut().take())
+}
+
+pub fn is_enabled() -> bool {
+    PROFILE.with(|p| p.borrow().is_some())
+}
+
+/// A copy of what has been recorded so far.
+pub fn snapshot() -> Option<Profile> {
+    PROFILE.with(|p| p.borrow().clone())
+}
+
+pub fn print_summary() {
+    match snapshot() {
+        Some(profile) => println!("{profile}"),
+        None => println!("profiler is not enabled"),
+    }
+}
+
+enum Kind {
+    Forward,
+    Backward,
+    BackwardPass,
+}
+
+/// Records the time elapsed between its creation and drop.
+pub(crate) struct Scope {
+    kind: Kind,
+    op: String,
+    start: Instant,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+
+        PROFILE.with(|p| {
+            if let Some(profile) = p.borrow_mut().as_mut() {
+                match self.kind {
+                    Kind::Forward => {
+                        let stats = profile.ops.entry(self.op.clone()).or_default();
+                        stats.count += 1;
+                        stats.forward += elapsed;
+                    }
+                    Kind::Backward => {
+                        profile.ops.entry(self.op.clone()).or_default().backward += elapsed;
+                    }
+                    Kind::BackwardPass => {
+                        profile.backward_passes += 1;
+                        profile.backward_total += elapsed;
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn scope(kind: Kind, op: &str) -> Option<Scope> {
+    is_enabled().then(|| Scope {
+        kind,
+        op: op.to_string(),
+        start: Instant::now(),
+    })
+}
+
+/// Time the construction of a node with the given op.
+pub(crate) fn forward(op: &str) -> Option<Scope> {
+    scope(Kind::Forward, op)
+}
+
+/// Time the backward function of a node with the given op.
+pub(crate) fn backward(op: &str) -> Option<Scope> {
+    scope(Kind::Backward, op)
+}
+
+/// Time a whole backward pass.
+pub(crate) fn backward_pass() -> Option<Scope> {
+    scope(Kind::BackwardPass, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::val::Val;
+
+    #[test]
+    fn counts_ops() {
+        super::enable();
+
+        let a = Val::new(2.0, "a");
+        let b = Val::new(-3.0, "b");
+        let c = Val::new(10.0, "c");
+        let l = (a * b + c).relu();
+        l.back_prop_gradient();
+
+        let profile = super::disable().unwrap();
+        assert_eq!(profile.ops()["*"].count, 1);
+        assert_eq!(profile.ops()["+"].count, 1);
+        assert_eq!(profile.ops()["ReLU"].count, 1);
+        assert_eq!(profile.backward_passes(), 1);
+        assert!(!super::is_enabled());
+    }
+}
@@ -0,0 +1,175 @@
+//! Gradual magnitude pruning, as in "To prune, or not to prune" (Zhu & Gupta): the smallest
+//! weights are zeroed a few at a time during training, so the rest of the model can adapt to
+//! the pruned ones instead of only losing accuracy when pruned afterwards.
+use crate::{callback::Callback, mlp::Mlp, val::Val};
+
+/// The weights of `model`, without the biases and PReLU slopes, which are never pruned.
+fn weights(model: &Mlp) -> Vec<Val> {
+    model
+        .named_parameters()
+        .into_iter()
+        .filter(|(name, _)| name.contains(".weights."))
+        .map(|(_, p)| p)
+        .collect()
+}
+
+/// The fraction of the weights of `model` that are zero.
+pub fn sparsity(model: &Mlp) -> f64 {
+    let weights = weights(model);
+    let zeros = weights.iter().filter(|w| w.data() == 0.0).count();
+    zeros as f64 / weights.len() as f64
+}
+
+/// A [`Callback`] pruning the weights of the smallest magnitude, up to a sparsity growing from
+/// `initial_sparsity` to `final_sparsity` between `begin_step` and `end_step` as
+/// `s_f + (s_i - s_f)(1 - progress)³`, fast at first while there are many redundant weights.
+///
+/// The pruned weights are picked again every `frequency` steps, and zeroed after every step in
+/// between so the optimizer can't bring them back.
+#[derive(Clone, Debug)]
+pub struct GradualPruning {
+    initial_sparsity: f64,
+    final_sparsity: f64,
+    begin_step: usize,
+    end_step: usize,
+    frequency: usize,
+    // Whether every weight is pruned, empty until the first update.
+    mask: Vec<bool>,
+}
+
+impl GradualPruning {
+    pub fn new(final_sparsity: f64, begin_step: usize, end_step: usize) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&final_sparsity),
+            "the sparsity must be between 0 and 1"
+        );
+        assert!(begin_step <= end_step, "pruning must begin before it ends");
+        Self {
+            initial_sparsity: 0.0,
+            final_sparsity,
+            begin_step,
+            end_step,
+            frequency: 10,
+            mask: vec![],
+        }
+    }
+
+    pub fn with_initial_sparsity(mut self, initial_sparsity: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&initial_sparsity),
+            "the sparsity must be between 0 and 1"
+        );
+        self.initial_sparsity = initial_sparsity;
+        self
+    }
+
+    /// Pick the pruned weights every `frequency` steps, 10 by default.
+    pub fn with_frequency(mut self, frequency: usize) -> Self {
+        assert!(frequency > 0, "the mask must be updated every step or less");
+        self.frequency = frequency;
+        self
+    }
+
+    /// The sparsity targeted at `step`, 0 before `begin_step`.
+    pub fn target_sparsity(&self, step: usize) -> f64 {
+        if step < self.begin_step {
+            return 0.0;
+        }
+        if step >= self.end_step {
+            return self.final_sparsity;
+        }
+        let progress = (step - self.begin_step) as f64 / (self.end_step - self.begin_step) as f64;
+        self.final_sparsity
+            + (self.initial_sparsity - self.final_sparsity) * (1.0 - progress).powi(3)
+    }
+
+    /// Prune the `target_sparsity(step)` fraction of `weights` with the smallest magnitudes.
+    fn update_mask(&mut self, weights: &[Val], step: usize) {
+        let pruned = (self.target_sparsity(step) * weights.len() as f64).round() as usize;
+
+        // The stable sort keeps already pruned weights, all zero, ahead of new ties.
+        let mut order: Vec<usize> = (0..weights.len()).collect();
+        order.sort_by(|&a, &b| weights[a].data().abs().total_cmp(&weights[b].data().abs()));
+
+        self.mask = vec![false; weights.len()];
+        for &i in &order[..pruned] {
+            self.mask[i] = true;
+        }
+    }
+}
+
+impl Callback for GradualPruning {
+    fn on_step(&mut self, model: &Mlp, step: usize) {
+        let weights = weights(model);
+
+        let in_range = (self.begin_step..=self.end_step).contains(&step);
+        let due = (step - self.begin_step.min(step)).is_multiple_of(self.frequency);
+        if in_range && (due || step == self.end_step) {
+            self.update_mask(&weights, step);
+        }
+
+        for (w, &pruned) in weights.iter().zip(&self.mask) {
+            if pruned {
+                w.set_data(0.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sparsity, GradualPruning};
+    use crate::{callback::Callback, estimator::MlpClassifier, mlp::Mlp};
+
+    #[test]
+    fn polynomial_schedule() {
+        let pruning = GradualPruning::new(0.8, 10, 20).with_initial_sparsity(0.1);
+        assert_eq!(pruning.target_sparsity(5), 0.0);
+        assert!((pruning.target_sparsity(10) - 0.1).abs() < 1e-12);
+        assert!((pruning.target_sparsity(15) - (0.8 - 0.7 / 8.0)).abs() < 1e-12);
+        assert_eq!(pruning.target_sparsity(20), 0.8);
+        assert_eq!(pruning.target_sparsity(100), 0.8);
+    }
+
+    #[test]
+    fn prunes_smallest_weights() {
+        let model = Mlp::new(4, vec![1]);
+        let params = model.parameters();
+        for (p, value) in params.iter().zip([0.5, -0.1, 2.0, 0.3, 1.0]) {
+            p.set_data(value);
+        }
+
+        let mut pruning = GradualPruning::new(0.5, 0, 0);
+        pruning.on_step(&model, 0);
+        let values: Vec<f64> = params.iter().map(|p| p.data()).collect();
+        // The bias isn't a weight, so it's kept.
+        assert_eq!(values, [0.5, 0.0, 2.0, 0.0, 1.0]);
+        assert_eq!(sparsity(&model), 0.5);
+
+        // Pruned weights stay pruned after the optimizer moves them.
+        params[1].set_data(3.0);
+        pruning.on_step(&model, 1);
+        assert_eq!(params[1].data(), 0.0);
+    }
+
+    #[test]
+    fn trains_sparse_model() {
+        let x = vec![
+            vec![-1.0, -1.0, 0.1],
+            vec![-0.8, -1.2, -0.2],
+            vec![1.0, 1.0, 0.2],
+            vec![0.9, 1.2, -0.1],
+        ];
+        let y = vec![0, 0, 1, 1];
+
+        let mut classifier = MlpClassifier::new(vec![8])
+            .with_learning_rate(0.1)
+            .with_epochs(300)
+            .with_callback(GradualPruning::new(0.5, 20, 100).with_frequency(5));
+        classifier.fit(&x, &y);
+
+        let model = classifier.model().unwrap();
+        assert_eq!(sparsity(model), 0.5);
+        assert_eq!(classifier.predict(&x), y);
+    }
+}
@@ -0,0 +1,221 @@
+//! Learning rate schedules, composable into multi-phase schedules with [`Sequential`].
+//!
+//! ```
+//! use neuron::schedule::{Constant, Cosine, Schedule, Sequential, Warmup};
+//!
+//! // Warm up for 10 steps, hold for 80, then decay over 10.
+//! let schedule = Sequential::new(vec![
+//!     Box::new(Warmup::new(0.0, 0.1, 10)),
+//!     Box::new(Constant::new(0.1, 80)),
+//!     Box::new(Cosine::new(0.1, 0.001, 10)),
+//! ]);
+//! assert_eq!(schedule.learning_rate(50), 0.1);
+//! ```
+use std::rc::Rc;
+
+pub trait Schedule {
+    fn learning_rate(&self, step: usize) -> f64;
+
+    /// How many steps the schedule lasts, after which it keeps its last learning rate.
+    fn steps(&self) -> usize;
+}
+
+/// Linear increase from `from` to `to`.
+#[derive(Clone, Copy, Debug)]
+pub struct Warmup {
+    from: f64,
+    to: f64,
+    steps: usize,
+}
+
+impl Warmup {
+    pub fn new(from: f64, to: f64, steps: usize) -> Self {
+        Self { from, to, steps }
+    }
+}
+
+impl Schedule for Warmup {
+    fn learning_rate(&self, step: usize) -> f64 {
+        if step >= self.steps {
+            self.to
+        } else {
+            self.from + (self.to - self.from) * step as f64 / self.steps as f64
+        }
+    }
+
+    fn steps(&self) -> usize {
+        self.steps
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Constant {
+    value: f64,
+    steps: usize,
+}
+
+impl Constant {
+    pub fn new(value: f64, steps: usize) -> Self {
+        Self { value, steps }
+    }
+}
+
+impl Schedule for Constant {
+    fn learning_rate(&self, _step: usize) -> f64 {
+        self.value
+    }
+
+    fn steps(&self) -> usize {
+        self.steps
+    }
+}
+
+/// Half a cosine wave from `from` down to `to`.
+#[derive(Clone, Copy, Debug)]
+pub struct Cosine {
+    from: f64,
+    to: f64,
+    steps: usize,
+}
+
+impl Cosine {
+    pub fn new(from: f64, to: f64, steps: usize) -> Self {
+        Self { from, to, steps }
+    }
+}
+
+impl Schedule for Cosine {
+    fn learning_rate(&self, step: usize) -> f64 {
+        if step >= self.steps {
+            return self.to;
+        }
+
+        let progress = step as f64 / self.steps as f64;
+        self.to + (self.from - self.to) * (1.0 + (std::f64::consts::PI * progress).cos()) / 2.0
+    }
+
+    fn steps(&self) -> usize {
+        self.steps
+    }
+}
+
+/// [`Cosine`] decays restarting from `from` every `cycle_steps` steps, `cycles` times, as in
+/// "SGDR: Stochastic Gradient Descent with Warm Restarts" (Loshchilov & Hutter). The model at
+/// the end of every cycle, in a trough of the learning rate, is a good snapshot to ensemble.
+#[derive(Clone, Copy, Debug)]
+pub struct CyclicCosine {
+    from: f64,
+    to: f64,
+    cycle_steps: usize,
+    cycles: usize,
+}
+
+impl CyclicCosine {
+    pub fn new(from: f64, to: f64, cycle_steps: usize, cycles: usize) -> Self {
+        assert!(cycle_steps > 0, "cycles need at least one step");
+        Self {
+            from,
+            to,
+            cycle_steps,
+            cycles,
+        }
+    }
+
+    pub fn cycle_steps(&self) -> usize {
+        self.cycle_steps
+    }
+}
+
+impl Schedule for CyclicCosine {
+    fn learning_rate(&self, step: usize) -> f64 {
+        if step >= self.steps() {
+            return self.to;
+        }
+        Cosine::new(self.from, self.to, self.cycle_steps).learning_rate(step % self.cycle_steps)
+    }
+
+    fn steps(&self) -> usize {
+        self.cycle_steps * self.cycles
+    }
+}
+
+/// Lets a schedule be shared, e.g. by an estimator that builds an optimizer per fit.
+impl<S: Schedule + ?Sized> Schedule for Rc<S> {
+    fn learning_rate(&self, step: usize) -> f64 {
+        (**self).learning_rate(step)
+    }
+
+    fn steps(&self) -> usize {
+        (**self).steps()
+    }
+}
+
+/// Run schedules one after the other, each seeing steps counted from its own start.
+pub struct Sequential {
+    phases: Vec<Box<dyn Schedule>>,
+}
+
+impl Sequential {
+    pub fn new(phases: Vec<Box<dyn Schedule>>) -> Self {
+        assert!(!phases.is_empty(), "a schedule needs at least one phase");
+        Self { phases }
+    }
+}
+
+impl Schedule for Sequential {
+    fn learning_rate(&self, mut step: usize) -> f64 {
+        let (last, phases) = self.phases.split_last().unwrap();
+
+        for phase in phases {
+            if step < phase.steps() {
+                return phase.learning_rate(step);
+            }
+            step -= phase.steps();
+        }
+        last.learning_rate(step)
+    }
+
+    fn steps(&self) -> usize {
+        self.phases.iter().map(|p| p.steps()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Constant, Cosine, CyclicCosine, Schedule, Sequential, Warmup};
+
+    #[test]
+    fn phases() {
+        assert_eq!(Warmup::new(0.0, 1.0, 4).learning_rate(1), 0.25);
+        assert_eq!(Warmup::new(0.0, 1.0, 4).learning_rate(9), 1.0);
+        assert_eq!(Cosine::new(1.0, 0.0, 4).learning_rate(0), 1.0);
+        assert!((Cosine::new(1.0, 0.0, 4).learning_rate(2) - 0.5).abs() < 1e-12);
+        assert_eq!(Cosine::new(1.0, 0.0, 4).learning_rate(4), 0.0);
+    }
+
+    #[test]
+    fn sequential() {
+        let schedule = Sequential::new(vec![
+            Box::new(Warmup::new(0.0, 1.0, 2)),
+            Box::new(Constant::new(1.0, 3)),
+            Box::new(Cosine::new(1.0, 0.0, 2)),
+        ]);
+
+        let rates: Vec<f64> = (0..9).map(|s| schedule.learning_rate(s)).collect();
+        assert_eq!(rates[..6], [0.0, 0.5, 1.0, 1.0, 1.0, 1.0]);
+        assert!((rates[6] - 0.5).abs() < 1e-12);
+        assert_eq!(rates[7..], [0.0, 0.0]);
+        assert_eq!(schedule.steps(), 7);
+    }
+
+    #[test]
+    fn cyclic() {
+        let schedule = CyclicCosine::new(1.0, 0.0, 4, 2);
+        let rates: Vec<f64> = (0..10).map(|s| schedule.learning_rate(s)).collect();
+        assert_eq!(rates[0], 1.0);
+        assert_eq!(rates[4], 1.0);
+        assert!((rates[6] - 0.5).abs() < 1e-12);
+        assert_eq!(rates[8..], [0.0, 0.0]);
+        assert_eq!(schedule.steps(), 8);
+    }
+}
@@ -0,0 +1,445 @@
+//! Utilities over sequences of vectors of [`Val`], one vector per position.
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+
+use crate::{
+    loss::{self, Reduction},
+    ops::argmax,
+    optim::Sgd,
+    val::Val,
+};
+
+/// The sinusoidal encodings of "Attention Is All You Need" for the positions `0..len`: feature
+/// `2i` of position `p` is `sin(p / 10000^(2i / dim))` and feature `2i + 1` the cosine of the
+/// same angle.
+pub fn sinusoidal_encodings(len: usize, dim: usize) -> Vec<Vec<f64>> {
+    (0..len)
+        .map(|p| {
+            (0..dim)
+                .map(|j| {
+                    let angle = p as f64 / 10000f64.powf((j - j % 2) as f64 / dim as f64);
+                    if j % 2 == 0 {
+                        angle.sin()
+                    } else {
+                        angle.cos()
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// `sequence` with the [`sinusoidal_encodings`] of its positions added, so models that treat
+/// the positions alike, like self-attention, can tell them apart.
+pub fn add_sinusoidal_encodings(sequence: &[Vec<Val>]) -> Vec<Vec<Val>> {
+    let dim = sequence.first().map_or(0, Vec::len);
+    sequence
+        .iter()
+        .zip(sinusoidal_encodings(sequence.len(), dim))
+        .map(|(xs, encoding)| {
+            assert_eq!(
+                xs.len(),
+                dim,
+                "every position needs the same number of features"
+            );
+            xs.iter().zip(encoding).map(|(x, e)| x + e).collect()
+        })
+        .collect()
+}
+
+/// A trained vector per position, added to the vector at that position.
+pub struct LearnedPositionalEncoding {
+    encodings: Vec<Vec<Val>>,
+}
+
+impl LearnedPositionalEncoding {
+    /// Encodings for sequences of up to `max_len` positions of `dim` features, initialized
+    /// close to zero.
+    pub fn new(max_len: usize, dim: usize) -> Self {
+        let mut rng = thread_rng();
+        Self {
+            encodings: (0..max_len)
+                .map(|_| {
+                    (0..dim)
+                        .map(|_| Val::from(rng.gen_range(-0.1..0.1)))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.encodings.len()
+    }
+
+    pub fn forward(&self, sequence: &[Vec<Val>]) -> Vec<Vec<Val>> {
+        assert!(
+            sequence.len() <= self.max_len(),
+            "the sequence is longer than the encoded positions"
+        );
+
+        sequence
+            .iter()
+            .zip(&self.encodings)
+            .map(|(xs, encoding)| {
+                assert_eq!(
+                    xs.len(),
+                    encoding.len(),
+                    "every position needs an encoding per feature"
+                );
+                xs.iter().zip(encoding).map(|(x, e)| x + e).collect()
+            })
+            .collect()
+    }
+
+    /// The encodings, position after position.
+    pub fn parameters(&self) -> Vec<Val> {
+        self.encodings.iter().flatten().cloned().collect()
+    }
+}
+
+/// Sequences of different lengths padded to a common one, see [`pad`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Padded<T> {
+    pub sequences: Vec<Vec<T>>,
+    /// Whether each position of each sequence holds a step of the sequence rather than
+    /// padding.
+    pub mask: Vec<Vec<bool>>,
+}
+
+/// Pad every one of `sequences`, e.g. a batch of a [`DataLoader`], with `padding` at the end
+/// up to the length of the longest one.
+///
+/// [`DataLoader`]: crate::loader::DataLoader
+pub fn pad<T: Clone, S: AsRef<[T]>>(sequences: &[S], padding: T) -> Padded<T> {
+    let len = sequences
+        .iter()
+        .map(|s| s.as_ref().len())
+        .max()
+        .unwrap_or(0);
+
+    let mut padded = Padded {
+        sequences: vec![],
+        mask: vec![],
+    };
+    for sequence in sequences {
+        let sequence = sequence.as_ref();
+        let mut steps = sequence.to_vec();
+        steps.resize(len, padding.clone());
+        padded.sequences.push(steps);
+
+        let mut mask = vec![true; sequence.len()];
+        mask.resize(len, false);
+        padded.mask.push(mask);
+    }
+    padded
+}
+
+/// The mean of the `losses` of every position the `mask` keeps, e.g. the per-step losses of a
+/// padded batch. Padded positions get no gradient. 0 if the mask keeps nothing.
+pub fn masked_mean(losses: &[Vec<Val>], mask: &[Vec<bool>]) -> Val {
+    assert_eq!(losses.len(), mask.len(), "every sequence needs a mask");
+
+    let mut total = Val::from(0.0);
+    let mut count = 0;
+    for (losses, mask) in losses.iter().zip(mask) {
+        assert_eq!(losses.len(), mask.len(), "every step needs a mask");
+        for (loss, keep) in losses.iter().zip(mask) {
+            if *keep {
+                total.accumulate(loss.clone());
+                count += 1;
+            }
+        }
+    }
+
+    total / count.max(1) as f64
+}
+
+/// Truncated back propagation through time for recurrent models built from a step function.
+///
+/// ```
+/// use neuron::{optim::Sgd, sequence::Bptt, val::Val};
+///
+/// let (w, u) = (Val::new(0.5, "w"), Val::new(0.5, "u"));
+/// let mut sgd = Sgd::new(vec![w.clone(), u.clone()], 0.1);
+/// let inputs = vec![vec![1.0], vec![0.0], vec![1.0]];
+/// let targets = vec![vec![1.0], vec![1.0], vec![1.0]];
+///
+/// let loss = Bptt::new(2).train(
+///     &mut sgd,
+///     vec![Val::from(0.0)],
+///     &inputs,
+///     &targets,
+///     |x, h| {
+///         let h = (&w * &x[0] + &u * &h[0]).tanh();
+///         (vec![h.clone()], vec![h])
+///     },
+///     |output, target| (&output[0] - target[0]).powi(2),
+/// );
+/// assert_eq!(sgd.steps(), 2);
+/// # assert!(loss > 0.0);
+/// ```
+pub struct Bptt {
+    window: usize,
+    teacher_forcing: f64,
+    rng: StdRng,
+}
+
+impl Bptt {
+    /// Back propagate and update the parameters every `window` steps, carrying the hidden state
+    /// over to the next window detached from the graph, so memory and the length gradients
+    /// flow back are bounded by the window.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "windows need at least one step");
+        Self {
+            window,
+            teacher_forcing: 1.0,
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+
+    /// Feed the true input of a step, rather than the output of the previous step, only with
+    /// probability `ratio`. The default of 1 always feeds the true inputs. For seq2seq models
+    /// whose input at every step is the target of the previous one, lower ratios train the
+    /// model on its own predictions as it sees them when generating.
+    pub fn with_teacher_forcing(mut self, ratio: f64, seed: u64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&ratio),
+            "the ratio must be between 0 and 1"
+        );
+        self.teacher_forcing = ratio;
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Train on one sequence and return its mean loss per step.
+    ///
+    /// `step` maps the input of a step and the hidden state to the output and the next hidden
+    /// state, `loss` compares an output with the target of its step.
+    pub fn train(
+        &mut self,
+        sgd: &mut Sgd,
+        hidden: Vec<Val>,
+        inputs: &[Vec<f64>],
+        targets: &[Vec<f64>],
+        mut step: impl FnMut(&[Val], &[Val]) -> (Vec<Val>, Vec<Val>),
+        mut loss: impl FnMut(&[Val], &[f64]) -> Val,
+    ) -> f64 {
+        assert_eq!(inputs.len(), targets.len(), "every step needs a target");
+        self.run(
+            sgd,
+            hidden,
+            inputs.len(),
+            |i, previous| match previous {
+                Some(output) => output.to_vec(),
+                None => inputs[i].iter().map(|x| Val::from(*x)).collect(),
+            },
+            |input: &Vec<Val>, hidden| step(input, hidden),
+            |output, i| loss(output, &targets[i]),
+        )
+    }
+
+    /// Train `step` to predict every token of `tokens` from the ones before it, and return the
+    /// mean cross-entropy per step.
+    ///
+    /// `step` maps a token and the hidden state to the logits of the next token and the next
+    /// hidden state. Without teacher forcing, a step gets the most likely token after the
+    /// previous one instead of the true one.
+    pub fn train_tokens(
+        &mut self,
+        sgd: &mut Sgd,
+        hidden: Vec<Val>,
+        tokens: &[usize],
+        mut step: impl FnMut(usize, &[Val]) -> (Vec<Val>, Vec<Val>),
+    ) -> f64 {
+        self.run(
+            sgd,
+            hidden,
+            tokens.len().saturating_sub(1),
+            |i, previous| match previous {
+                Some(logits) => argmax(&logits.iter().map(Val::data).collect::<Vec<f64>>()),
+                None => tokens[i],
+            },
+            |token, hidden| step(*token, hidden),
+            |logits, i| {
+                loss::cross_entropy(&[logits.to_vec()], &[tokens[i + 1]], Reduction::Sum).remove(0)
+            },
+        )
+    }
+
+    /// The loop of [`Bptt::train`] over `len` steps. Step `i` gets `input(i, None)`, or
+    /// `input(i, Some(previous_output))` without teacher forcing.
+    fn run<I>(
+        &mut self,
+        sgd: &mut Sgd,
+        mut hidden: Vec<Val>,
+        len: usize,
+        input: impl Fn(usize, Option<&[Val]>) -> I,
+        mut step: impl FnMut(&I, &[Val]) -> (Vec<Val>, Vec<Val>),
+        mut loss: impl FnMut(&[Val], usize) -> Val,
+    ) -> f64 {
+        let mut total_loss = 0.0;
+        let mut previous_output: Option<Vec<Val>> = None;
+        for start in (0..len).step_by(self.window) {
+            let mut window_loss = Val::from(0.0);
+            for i in start..(start + self.window).min(len) {
+                let fed = previous_output
+                    .take()
+                    .filter(|_| self.rng.gen::<f64>() >= self.teacher_forcing);
+                let input = input(i, fed.as_deref());
+
+                let (output, next_hidden) = step(&input, &hidden);
+                window_loss.accumulate(loss(&output, i));
+                hidden = next_hidden;
+                previous_output = Some(output);
+            }
+
+            sgd.zero_grad();
+            window_loss.back_prop_gradient();
+            sgd.step();
+            total_loss += window_loss.data();
+
+            hidden = hidden.iter().map(Val::detach).collect();
+            previous_output = previous_output.map(|o| o.iter().map(Val::detach).collect());
+            window_loss.free_graph();
+        }
+
+        total_loss / len.max(1) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_sinusoidal_encodings, masked_mean, pad, sinusoidal_encodings, Bptt,
+        LearnedPositionalEncoding,
+    };
+    use crate::{optim::Sgd, val::Val};
+
+    fn zeros(len: usize, dim: usize) -> Vec<Vec<Val>> {
+        (0..len)
+            .map(|_| (0..dim).map(|_| Val::from(0.0)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn sinusoidal() {
+        let encodings = sinusoidal_encodings(3, 4);
+        assert_eq!(encodings[0], vec![0.0, 1.0, 0.0, 1.0]);
+        assert!((encodings[2][0] - 2f64.sin()).abs() < 1e-12);
+        assert!((encodings[2][3] - 0.02f64.cos()).abs() < 1e-12);
+
+        let encoded = add_sinusoidal_encodings(&zeros(3, 4));
+        assert_eq!(encoded[2][1].data(), encodings[2][1]);
+    }
+
+    #[test]
+    fn learned() {
+        let positional = LearnedPositionalEncoding::new(4, 2);
+        let encoded = positional.forward(&zeros(3, 2));
+        assert_eq!(encoded.len(), 3);
+        assert_eq!(positional.parameters().len(), 8);
+
+        let total: Val = encoded.into_iter().flatten().sum();
+        total.back_prop_gradient();
+        let gradients: Vec<f64> = positional.parameters().iter().map(Val::gradient).collect();
+        assert_eq!(gradients, vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn padding_and_masking() {
+        let padded = pad(&[vec![1, 2, 3], vec![4]], 0);
+        assert_eq!(padded.sequences, vec![vec![1, 2, 3], vec![4, 0, 0]]);
+        assert_eq!(
+            padded.mask,
+            vec![vec![true, true, true], vec![true, false, false]]
+        );
+
+        let losses: Vec<Vec<Val>> = padded
+            .sequences
+            .iter()
+            .map(|s| s.iter().map(|x| Val::from(*x as f64)).collect())
+            .collect();
+        let mean = masked_mean(&losses, &padded.mask);
+        assert_eq!(mean.data(), 2.5);
+
+        mean.back_prop_gradient();
+        assert_eq!(losses[1][0].gradient(), 0.25);
+        assert_eq!(losses[1][1].gradient(), 0.0);
+    }
+
+    #[test]
+    fn truncated_bptt() {
+        let w = Val::new(0.5, "w");
+        let mut sgd = Sgd::new(vec![w.clone()], 0.1);
+        let inputs: Vec<Vec<f64>> = (0..5).map(|i| vec![i as f64]).collect();
+
+        let mut seen = vec![];
+        let mut hidden_sizes = vec![];
+        Bptt::new(2).train(
+            &mut sgd,
+            vec![Val::from(0.0)],
+            &inputs,
+            &inputs,
+            |x, h| {
+                seen.push(x[0].data());
+                hidden_sizes.push(h[0].topological_order().len());
+                let h = &w * &x[0] + &h[0];
+                (vec![h.clone()], vec![h])
+            },
+            |output, target| (&output[0] - target[0]).powi(2),
+        );
+
+        // Three windows of 2, 2 and 1 steps, each starting from a detached hidden state.
+        assert_eq!(sgd.steps(), 3);
+        assert_eq!(seen, vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+        let window_starts: Vec<usize> = hidden_sizes.iter().step_by(2).copied().collect();
+        assert_eq!(window_starts, vec![1, 1, 1]);
+        assert!(hidden_sizes[1] > 1);
+    }
+
+    #[test]
+    fn teacher_forcing() {
+        let inputs = vec![vec![1.0], vec![2.0], vec![3.0]];
+        let mut sgd = Sgd::new(vec![], 0.1);
+
+        let mut seen = vec![];
+        Bptt::new(3).with_teacher_forcing(0.0, 1).train(
+            &mut sgd,
+            vec![],
+            &inputs,
+            &inputs,
+            |x, _| {
+                seen.push(x[0].data());
+                (vec![&x[0] * 10.0], vec![])
+            },
+            |output, target| &output[0] - target[0],
+        );
+
+        // Only the first step gets its true input, the others get the previous output.
+        assert_eq!(seen, vec![1.0, 10.0, 100.0]);
+    }
+
+    #[test]
+    fn token_bptt() {
+        let w = Val::new(1.0, "w");
+        let mut sgd = Sgd::new(vec![w.clone()], 0.1);
+
+        let mut seen = vec![];
+        let loss = Bptt::new(2).with_teacher_forcing(0.0, 1).train_tokens(
+            &mut sgd,
+            vec![],
+            &[2, 0, 1, 1],
+            |token, _| {
+                seen.push(token);
+                // Token 1 is always the most likely next one.
+                (vec![Val::from(0.0), w.clone(), Val::from(-1.0)], vec![])
+            },
+        );
+
+        // Three steps predicting the tokens after the first one, in windows of 2 and 1. The
+        // steps after the first are fed the predicted token 1, not the true 0 and 1.
+        assert_eq!(seen, vec![2, 1, 1]);
+        assert_eq!(sgd.steps(), 2);
+        assert!(loss > 0.0);
+    }
+}
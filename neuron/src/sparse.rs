@@ -0,0 +1,290 @@
+//! Frozen models storing only their non-zero weights, e.g. after [`GradualPruning`], so pruned
+//! models are smaller on disk and faster to evaluate.
+//!
+//! [`GradualPruning`]: crate::prune::GradualPruning
+use std::{fs, io, path::Path};
+
+use crate::{
+    layer::Layer,
+    mlp::{self, invalid, parse, Mlp},
+    neuron::{Activation, Neuron},
+    ops::argmax,
+    val::Val,
+};
+
+/// The weights of a layer in compressed sparse row form: the non-zero weights of neuron `j`
+/// are `values[offsets[j]..offsets[j + 1]]`, for the inputs at the same positions of
+/// `columns`.
+#[derive(Clone, Debug, PartialEq)]
+struct SparseLayer {
+    num_inputs: usize,
+    activation: Activation,
+    offsets: Vec<usize>,
+    columns: Vec<usize>,
+    values: Vec<f64>,
+    biases: Vec<f64>,
+    // The learned slope of every neuron for PReLU, empty otherwise.
+    slopes: Vec<f64>,
+}
+
+impl SparseLayer {
+    fn new(num_inputs: usize, activation: Activation) -> Self {
+        Self {
+            num_inputs,
+            activation,
+            offsets: vec![0],
+            columns: vec![],
+            values: vec![],
+            biases: vec![],
+            slopes: vec![],
+        }
+    }
+
+    fn num_outputs(&self) -> usize {
+        self.biases.len()
+    }
+
+    /// Append a neuron with the non-zero `weights`, by input.
+    fn push(&mut self, weights: impl IntoIterator<Item = (usize, f64)>, bias: f64) {
+        for (column, value) in weights {
+            if value != 0.0 {
+                self.columns.push(column);
+                self.values.push(value);
+            }
+        }
+        self.offsets.push(self.values.len());
+        self.biases.push(bias);
+    }
+
+    fn forward(&self, inputs: &[f64]) -> Vec<f64> {
+        (0..self.num_outputs())
+            .map(|j| {
+                let row = self.offsets[j]..self.offsets[j + 1];
+                let sum: f64 = self.columns[row.clone()]
+                    .iter()
+                    .zip(&self.values[row])
+                    .map(|(c, v)| inputs[*c] * v)
+                    .sum();
+                // A PReLU neuron is a leaky ReLU with its learned slope.
+                let activation = match self.slopes.get(j) {
+                    Some(slope) => Activation::LeakyReLU(*slope),
+                    None => self.activation,
+                };
+                activation.apply_f64(self.biases[j] + sum)
+            })
+            .collect()
+    }
+}
+
+/// A frozen copy of an [`Mlp`] without its zero weights, evaluated on plain numbers.
+///
+/// ```
+/// use neuron::{mlp::Mlp, sparse::SparseMlp};
+///
+/// let mlp = Mlp::new(3, vec![4, 2]);
+/// mlp.parameters()[0].set_data(0.0);
+/// let sparse = SparseMlp::from_mlp(&mlp);
+/// assert_eq!(sparse.num_nonzero(), 3 * 4 + 4 * 2 - 1);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct SparseMlp {
+    layers: Vec<SparseLayer>,
+}
+
+impl SparseMlp {
+    /// The version in the header of [`SparseMlp::save`], changed with the format.
+    const FORMAT_VERSION: u32 = 1;
+
+    pub fn from_mlp(model: &Mlp) -> Self {
+        let layers = model
+            .layers()
+            .iter()
+            .map(|layer| {
+                let mut sparse = SparseLayer::new(layer.num_inputs(), layer.activation());
+                for neuron in layer.neurons() {
+                    let weights = neuron.weights().iter().map(Val::data).enumerate();
+                    sparse.push(weights, neuron.bias().data());
+                    sparse.slopes.extend(neuron.slope().map(Val::data));
+                }
+                sparse
+            })
+            .collect();
+        Self { layers }
+    }
+
+    /// A trainable model with the same outputs, the pruned weights being zero.
+    pub fn to_mlp(&self) -> Mlp {
+        let layers: Vec<Layer> = self
+            .layers
+            .iter()
+            .map(|l| Layer::new(l.num_inputs, l.num_outputs()).with_activation(l.activation))
+            .collect();
+
+        for (sparse, layer) in self.layers.iter().zip(&layers) {
+            for (j, neuron) in layer.neurons().iter().enumerate() {
+                set_neuron(sparse, j, neuron);
+            }
+        }
+        Mlp::from_layers(layers)
+    }
+
+    /// The number of weights kept, out of one per input of every neuron.
+    pub fn num_nonzero(&self) -> usize {
+        self.layers.iter().map(|l| l.values.len()).sum()
+    }
+
+    pub fn forward(&self, xs: &[f64]) -> Vec<f64> {
+        let mut outputs = xs.to_vec();
+        for layer in &self.layers {
+            assert_eq!(
+                outputs.len(),
+                layer.num_inputs,
+                "the input has the wrong size"
+            );
+            outputs = layer.forward(&outputs);
+        }
+        outputs
+    }
+
+    /// See [`Mlp::predict_class`].
+    pub fn predict_class(&self, xs: &[f64]) -> usize {
+        argmax(&self.forward(xs))
+    }
+
+    /// Write the model to `path` as text like [`Mlp::save`], with one line per neuron holding
+    /// its bias, its PReLU slope if any, then `input:weight` for every non-zero weight.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let num_inputs = self.layers.first().map_or(0, |l| l.num_inputs);
+        let shapes = self.layers.iter().map(|l| (l.num_outputs(), l.activation));
+        let mut out = mlp::write_header("sparse-mlp", Self::FORMAT_VERSION, num_inputs, shapes);
+        for layer in &self.layers {
+            for j in 0..layer.num_outputs() {
+                let mut row = vec![format!("{:?}", layer.biases[j])];
+                row.extend(layer.slopes.get(j).map(|s| format!("{s:?}")));
+                for k in layer.offsets[j]..layer.offsets[j + 1] {
+                    row.push(format!("{}:{:?}", layer.columns[k], layer.values[k]));
+                }
+                out.push_str(&row.join(" "));
+                out.push('\n');
+            }
+        }
+        fs::write(path, out)
+    }
+
+    /// Read a model written by [`SparseMlp::save`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines().peekable();
+        let shapes = mlp::read_header(&mut lines, "sparse-mlp", Self::FORMAT_VERSION)?;
+
+        let mut layers = vec![];
+        for (num_inputs, size, activation) in shapes {
+            let mut layer = SparseLayer::new(num_inputs, activation);
+            for _ in 0..size {
+                let row = lines.next().ok_or_else(|| invalid("missing neuron"))?;
+                let mut fields = row.split(' ');
+
+                let bias = parse(fields.next(), "bias")?;
+                if let Activation::PReLU(_) = activation {
+                    layer.slopes.push(parse(fields.next(), "slope")?);
+                }
+                let weights = fields
+                    .map(|f| {
+                        let (column, value) = f.split_once(':').unwrap_or((f, ""));
+                        let column: usize = parse(Some(column), "weight input")?;
+                        if column >= num_inputs {
+                            return Err(invalid("invalid weight input"));
+                        }
+                        Ok((column, parse(Some(value), "weight")?))
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                layer.push(weights, bias);
+            }
+            layers.push(layer);
+        }
+
+        if lines.next().is_some() {
+            return Err(invalid("too many neurons"));
+        }
+        Ok(Self { layers })
+    }
+}
+
+/// Give `neuron` the parameters of neuron `j` of `sparse`.
+fn set_neuron(sparse: &SparseLayer, j: usize, neuron: &Neuron) {
+    for w in neuron.weights() {
+        w.set_data(0.0);
+    }
+    for k in sparse.offsets[j]..sparse.offsets[j + 1] {
+        neuron.weights()[sparse.columns[k]].set_data(sparse.values[k]);
+    }
+    neuron.bias().set_data(sparse.biases[j]);
+    if let (Some(slope), Some(value)) = (neuron.slope(), sparse.slopes.get(j)) {
+        slope.set_data(*value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SparseMlp;
+    use crate::{
+        callback::Callback, layer::Layer, mlp::Mlp, neuron::Activation, prune::GradualPruning,
+        val::Val,
+    };
+
+    fn pruned_model() -> Mlp {
+        let model = Mlp::from_layers(vec![
+            Layer::new(3, 4).with_activation(Activation::PReLU(0.2)),
+            Layer::new(4, 2).with_activation(Activation::Linear),
+        ]);
+        // Move the slopes away from their initial value.
+        for (name, p) in model.named_parameters() {
+            if name.ends_with("slope") {
+                p.set_data(p.data() + 0.1);
+            }
+        }
+        GradualPruning::new(0.6, 0, 0).on_step(&model, 0);
+        model
+    }
+
+    #[test]
+    fn same_outputs() {
+        let model = pruned_model();
+        let sparse = SparseMlp::from_mlp(&model);
+        // 20 weights, 60% pruned.
+        assert_eq!(sparse.num_nonzero(), 8);
+
+        for xs in [[1.0, -2.0, 0.5], [-0.3, 0.7, -1.5]] {
+            let dense: Vec<f64> = model.forward(&xs).iter().map(Val::data).collect();
+            let outputs = sparse.forward(&xs);
+            for (d, s) in dense.iter().zip(&outputs) {
+                assert!((d - s).abs() < 1e-12);
+            }
+            assert_eq!(sparse.predict_class(&xs), model.predict_class(&xs));
+        }
+
+        let dense: Vec<f64> = model.parameters().iter().map(Val::data).collect();
+        let restored: Vec<f64> = sparse.to_mlp().parameters().iter().map(Val::data).collect();
+        assert_eq!(restored, dense);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let sparse = SparseMlp::from_mlp(&pruned_model());
+        let path = std::env::temp_dir().join(format!("neuron-sparse-{}.mlp", std::process::id()));
+        sparse.save(&path).unwrap();
+        let loaded = SparseMlp::load(&path);
+        assert_eq!(loaded.unwrap(), sparse);
+
+        // Another version, or a dense model, isn't read as a sparse one.
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, text.replacen("sparse-mlp 1 ", "sparse-mlp 2 ", 1)).unwrap();
+        let newer = SparseMlp::load(&path).err().unwrap();
+        pruned_model().save(&path).unwrap();
+        let dense = SparseMlp::load(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(newer.to_string(), "unsupported sparse-mlp format version 2");
+        assert_eq!(dense.to_string(), "missing sparse-mlp header");
+    }
+}
@@ -0,0 +1,128 @@
+//! Helpers for testing and benchmarking the engine.
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{ops::l2_norm, val::Val};
+
+/// A randomly generated graph, see [`random_graph`].
+pub struct RandomGraph {
+    /// The leaves of the graph.
+    pub inputs: Vec<Val>,
+    pub output: Val,
+}
+
+/// Generate a random DAG of `size` op nodes over a handful of inputs, using every op of [`Val`].
+///
+/// Nodes pick their operands among all the nodes created before them, so the graph has shared
+/// subexpressions and nodes used as both operands of an op. The output sums the nodes nothing
+/// else depends on, so every node contributes to it. The same seed always gives the same graph.
+pub fn random_graph(seed: u64, size: usize) -> RandomGraph {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let num_inputs = 2 + size / 4;
+    let inputs: Vec<Val> = (0..num_inputs)
+        .map(|i| Val::new(rng.gen_range(-2.0..2.0), &format!("x{i}")))
+        .collect();
+
+    let mut nodes = inputs.clone();
+    let mut used = vec![false; nodes.len()];
+    for _ in 0..size {
+        let a = rng.gen_range(0..nodes.len());
+        let b = rng.gen_range(0..nodes.len());
+        used[a] = true;
+        used[b] = true;
+
+        let (x, y) = (nodes[a].clone(), nodes[b].clone());
+        let node = match rng.gen_range(0..27) {
+            0 => x + y,
+            1 => x * y,
+            2 => x.relu(),
+            3 => -x,
+            4 => x.tanh(),
+            // Squashed first so long chains of exponentials can't overflow.
+            5 => x.tanh().exp(),
+            6 => (x.pow(&Val::from(2.0)) + Val::from(1.0)).ln(),
+            7 => x.sigmoid(),
+            8 => x.powi(rng.gen_range(1..=3)),
+            9 => x.abs(),
+            10 => x.max(&y),
+            11 => x.clamp(-1.0, 1.0),
+            12 => (x.powi(2) + 1.0).sqrt(),
+            13 => x.leaky_relu(0.1),
+            14 => x.elu(1.0),
+            15 => x.gelu(),
+            16 => x.silu(),
+            17 => x.mish(),
+            18 => x.prelu(&y),
+            19 => x.sin(),
+            20 => x.cos(),
+            21 => (x.powi(2) + 1.0).recip(),
+            22 => x.sign() * y,
+            23 => x.hard_tanh(),
+            24 => x.hard_sigmoid(),
+            25 => l2_norm(&[x, y]),
+            // Integer exponents keep the result defined for negative bases.
+            _ => x.pow(&Val::from(rng.gen_range(1..=3) as f64)),
+        };
+        nodes.push(node);
+        used.push(false);
+    }
+
+    let output = nodes
+        .into_iter()
+        .zip(used)
+        .filter(|(_, used)| !used)
+        .map(|(node, _)| node)
+        .reduce(|acc, node| acc + node)
+        .unwrap();
+
+    RandomGraph { inputs, output }
+}
+
+/// Estimate the derivative of `output` with respect to the leaf `input` with central
+/// differences, re-evaluating the graph in place. The data of `input` is restored afterwards.
+pub fn numerical_gradient(output: &Val, input: &Val, h: f64) -> f64 {
+    let x = input.data();
+
+    input.set_data(x + h);
+    output.recompute();
+    let above = output.data();
+
+    input.set_data(x - h);
+    output.recompute();
+    let below = output.data();
+
+    input.set_data(x);
+    output.recompute();
+
+    (above - below) / (2.0 * h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{numerical_gradient, random_graph};
+
+    #[test]
+    fn deterministic() {
+        let a = random_graph(7, 20);
+        let b = random_graph(7, 20);
+        assert_eq!(a.output.data(), b.output.data());
+        assert_eq!(a.output.to_json_graph(), b.output.to_json_graph());
+    }
+
+    #[test]
+    fn backward_matches_finite_differences() {
+        for seed in 0..50 {
+            let graph = random_graph(seed, 12);
+            graph.output.back_prop_gradient();
+
+            for input in &graph.inputs {
+                let expected = numerical_gradient(&graph.output, input, 1e-6);
+                let tolerance = 1e-4 * expected.abs().max(1.0);
+                assert!(
+                    (input.gradient() - expected).abs() < tolerance,
+                    "seed {seed}, {input}: expected gradient {expected}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! An opt-in, per-thread trace of the construction of the graph, to find where unexpected NaNs
+//! or giant graphs come from.
+//!
+//! While enabled, every node created records its op, the labels of its parents and its data in
+//! a ring buffer keeping the latest records. If the thread panics, e.g. on a NaN assertion, the
+//! buffer is printed to stderr after the panic message.
+//!
+//! ```
+//! use neuron::{trace, val::Val};
+//!
+//! trace::enable(100);
+//! let a = Val::new(2.0, "a");
+//! let _ = a * Val::from(3.0);
+//! let records = trace::disable().unwrap();
+//! assert_eq!(records.len(), 3);
+//! assert_eq!(records[2].to_string(), "#2 * [a, _] = 6");
+//! ```
+use std::{cell::RefCell, collections::VecDeque, fmt::Display, panic, sync::Once};
+
+thread_local! {
+    static TRACE: RefCell<Option<Trace>> = const { RefCell::new(None) };
+}
+
+/// A node as it was created.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// How many nodes were traced before this one.
+    pub index: usize,
+    /// `None` for leaves.
+    pub op: Option<String>,
+    /// The labels of the parents, `None` for unlabeled ones.
+    pub parents: Vec<Option<String>>,
+    pub data: f64,
+}
+
+/// Labels are filled in after the node is created, so they are shown by the parents of later
+/// nodes rather than by the record of the node itself.
+impl Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parents: Vec<&str> = self
+            .parents
+            .iter()
+            .map(|l| l.as_deref().unwrap_or("_"))
+            .collect();
+        write!(
+            f,
+            "#{} {} [{}] = {}",
+            self.index,
+            self.op.as_deref().unwrap_or("leaf"),
+            parents.join(", "),
+            self.data
+        )
+    }
+}
+
+struct Trace {
+    records: VecDeque<Record>,
+    capacity: usize,
+    total: usize,
+}
+
+/// Start tracing, keeping the last `capacity` records and discarding anything recorded before.
+pub fn enable(capacity: usize) {
+    assert!(capacity > 0, "the trace needs room for at least one record");
+
+    // The hook is process wide, but only prints the trace of the panicking thread.
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            dump();
+        }));
+    });
+
+    TRACE.with(|t| {
+        *t.borrow_mut() = Some(Trace {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+            total: 0,
+        })
+    });
+}
+
+/// Stop tracing and return the records kept, oldest first.
+pub fn disable() -> Option<Vec<Record>> {
+    TRACE.with(|t| t.borrow_mut().take().map(|trace| trace.records.into()))
+}
+
+pub fn is_enabled() -> bool {
+    TRACE.with(|t| t.borrow().is_some())
+}
+
+/// A copy of the records kept so far, oldest first.
+pub fn snapshot() -> Option<Vec<Record>> {
+    TRACE.with(|t| {
+        t.borrow()
+            .as_ref()
+            .map(|trace| trace.records.iter().cloned().collect())
+    })
+}
+
+/// Print the records kept so far to stderr, if tracing is enabled.
+pub fn dump() {
+    // The panic may have happened while the trace was borrowed.
+    let _ = TRACE.try_with(|t| {
+        let Ok(trace) = t.try_borrow() else {
+            return;
+        };
+        if let Some(trace) = trace.as_ref() {
+            eprintln!(
+                "last {} of {} traced node(s):",
+                trace.records.len(),
+                trace.total
+            );
+            for record in &trace.records {
+                eprintln!("  {record}");
+            }
+        }
+    });
+}
+
+/// Record a newly created node.
+pub(crate) fn record(op: Option<&str>, parents: Vec<Option<String>>, data: f64) {
+    TRACE.with(|t| {
+        let mut trace = t.borrow_mut();
+        let Some(trace) = trace.as_mut() else {
+            return;
+        };
+
+        if trace.records.len() == trace.capacity {
+            trace.records.pop_front();
+        }
+        trace.records.push_back(Record {
+            index: trace.total,
+            op: op.map(str::to_string),
+            parents,
+            data,
+        });
+        trace.total += 1;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::val::Val;
+
+    #[test]
+    fn keeps_latest_records() {
+        super::enable(2);
+        let a = Val::new(1.0, "a");
+        let b = Val::new(0.0, "b");
+        let _ = a.clone() / b.clone();
+        let snapshot = super::snapshot().unwrap();
+        let records = super::disable().unwrap();
+        assert_eq!(records, snapshot);
+
+        // The division is a product with the reciprocal of b.
+        let ops: Vec<Option<&str>> = records.iter().map(|r| r.op.as_deref()).collect();
+        assert_eq!(ops, [Some("recip"), Some("*")]);
+        assert_eq!(records[0].parents, [Some("b".to_string())]);
+        assert_eq!(records[1].index, 3);
+        assert_eq!(records[1].data, f64::INFINITY);
+
+        assert!(!super::is_enabled());
+        let _ = a + b;
+        assert_eq!(super::disable(), None);
+    }
+}
@@ -0,0 +1,395 @@
+//! A minimal pre-norm transformer over sequences of vectors of [`Val`], one vector per
+//! position, as in GPT-2.
+use crate::{
+    layer::Layer,
+    neuron::Activation,
+    ops::{dot, softmax},
+    val::Val,
+};
+
+/// Normalizes every vector to zero mean and unit variance, then scales and shifts every
+/// feature by a learned gain and bias.
+pub struct LayerNorm {
+    gain: Vec<Val>,
+    bias: Vec<Val>,
+}
+
+impl LayerNorm {
+    /// Smaller variances are clamped to it, so constant vectors don't divide by zero.
+    const EPS: f64 = 1e-5;
+
+    /// The gains start at 1 and the biases at 0.
+    pub fn new(dim: usize) -> Self {
+        Self {
+            gain: (0..dim).map(|_| Val::from(1.0)).collect(),
+            bias: (0..dim).map(|_| Val::from(0.0)).collect(),
+        }
+    }
+
+    pub fn forward(&self, xs: &[Val]) -> Vec<Val> {
+        assert_eq!(xs.len(), self.gain.len(), "the input has the wrong size");
+
+        let n = xs.len() as f64;
+        let mean = xs.iter().cloned().sum::<Val>() / n;
+        let centered: Vec<Val> = xs.iter().map(|x| x - &mean).collect();
+        let variance = centered.iter().map(|c| c.powi(2)).sum::<Val>() / n;
+        let inv_std = Val::from(1.0).div_eps(&variance, Self::EPS).sqrt();
+
+        centered
+            .iter()
+            .zip(self.gain.iter().zip(&self.bias))
+            .map(|(c, (g, b))| c * &inv_std * g + b)
+            .collect()
+    }
+
+    /// The gains followed by the biases.
+    pub fn parameters(&self) -> Vec<Val> {
+        self.gain.iter().chain(&self.bias).cloned().collect()
+    }
+}
+
+/// Single-head scaled dot-product self-attention: every position gets a mix of the values of
+/// the positions whose keys match its query best.
+pub struct SelfAttention {
+    query: Layer,
+    key: Layer,
+    value: Layer,
+    output: Layer,
+    causal: bool,
+}
+
+impl SelfAttention {
+    pub fn new(dim: usize) -> Self {
+        let projection = || Layer::new(dim, dim).with_activation(Activation::Linear);
+        Self {
+            query: projection(),
+            key: projection(),
+            value: projection(),
+            output: projection(),
+            causal: false,
+        }
+    }
+
+    /// Only let every position attend to itself and the positions before it, so a model can
+    /// be trained to predict the next position without seeing it.
+    pub fn with_causal_mask(mut self) -> Self {
+        self.causal = true;
+        self
+    }
+
+    pub fn forward(&self, sequence: &[Vec<Val>]) -> Vec<Vec<Val>> {
+        self.forward_masked(sequence, &vec![true; sequence.len()])
+    }
+
+    /// Like [`SelfAttention::forward`], but no position attends to the ones `mask` doesn't
+    /// keep, e.g. the padding of a batch from [`pad`]. Their scores are set to -∞ before the
+    /// softmax, like [`masked_mean`] skips their losses.
+    ///
+    /// [`pad`]: crate::sequence::pad
+    /// [`masked_mean`]: crate::sequence::masked_mean
+    pub fn forward_masked(&self, sequence: &[Vec<Val>], mask: &[bool]) -> Vec<Vec<Val>> {
+        let (weights, values) = self.attend(sequence, mask);
+        weights
+            .iter()
+            .map(|weights| {
+                let mixed: Vec<Val> = (0..self.value.num_outputs())
+                    .map(|d| {
+                        weights
+                            .iter()
+                            .zip(&values)
+                            .map(|(w, value)| w * &value[d])
+                            .sum()
+                    })
+                    .collect();
+                self.output.forward(&mixed)
+            })
+            .collect()
+    }
+
+    /// The weights every position gives to the positions it can see, summing to 1.
+    pub fn attention_weights(&self, sequence: &[Vec<Val>], mask: &[bool]) -> Vec<Vec<Val>> {
+        self.attend(sequence, mask).0
+    }
+
+    /// The attention weights and the values of every position.
+    fn attend(&self, sequence: &[Vec<Val>], mask: &[bool]) -> (Vec<Vec<Val>>, Vec<Vec<Val>>) {
+        assert_eq!(mask.len(), sequence.len(), "every position needs a mask");
+
+        let queries: Vec<Vec<Val>> = sequence.iter().map(|x| self.query.forward(x)).collect();
+        let keys: Vec<Vec<Val>> = sequence.iter().map(|x| self.key.forward(x)).collect();
+        let values: Vec<Vec<Val>> = sequence.iter().map(|x| self.value.forward(x)).collect();
+        let scale = (self.query.num_outputs() as f64).sqrt();
+
+        let weights = queries
+            .iter()
+            .enumerate()
+            .map(|(i, query)| {
+                let visible = if self.causal { i + 1 } else { keys.len() };
+                assert!(
+                    mask[..visible].contains(&true),
+                    "position {i} can't attend to any position"
+                );
+                let scores: Vec<Val> = keys[..visible]
+                    .iter()
+                    .zip(mask)
+                    .map(|(key, keep)| {
+                        if *keep {
+                            dot(query, key) / scale
+                        } else {
+                            Val::from(f64::NEG_INFINITY)
+                        }
+                    })
+                    .collect();
+                softmax(&scores)
+            })
+            .collect();
+        (weights, values)
+    }
+
+    /// The query, key, value and output projections, in that order.
+    pub fn parameters(&self) -> Vec<Val> {
+        [&self.query, &self.key, &self.value, &self.output]
+            .iter()
+            .flat_map(|l| l.parameters())
+            .collect()
+    }
+}
+
+/// Self-attention followed by a position-wise MLP with a GELU hidden layer, each applied to a
+/// [`LayerNorm`] of its input and added back to it.
+pub struct TransformerBlock {
+    attention_norm: LayerNorm,
+    attention: SelfAttention,
+    mlp_norm: LayerNorm,
+    hidden: Layer,
+    output: Layer,
+}
+
+impl TransformerBlock {
+    /// A block over vectors of `dim` features, with `hidden_size` neurons in the hidden layer
+    /// of the MLP, usually `4 * dim`.
+    pub fn new(dim: usize, hidden_size: usize) -> Self {
+        Self {
+            attention_norm: LayerNorm::new(dim),
+            attention: SelfAttention::new(dim),
+            mlp_norm: LayerNorm::new(dim),
+            hidden: Layer::new(dim, hidden_size).with_activation(Activation::GELU),
+            output: Layer::new(hidden_size, dim).with_activation(Activation::Linear),
+        }
+    }
+
+    /// See [`SelfAttention::with_causal_mask`].
+    pub fn with_causal_mask(mut self) -> Self {
+        self.attention = self.attention.with_causal_mask();
+        self
+    }
+
+    pub fn forward(&self, sequence: &[Vec<Val>]) -> Vec<Vec<Val>> {
+        self.forward_masked(sequence, &vec![true; sequence.len()])
+    }
+
+    /// See [`SelfAttention::forward_masked`].
+    pub fn forward_masked(&self, sequence: &[Vec<Val>], mask: &[bool]) -> Vec<Vec<Val>> {
+        let normalized: Vec<Vec<Val>> = sequence
+            .iter()
+            .map(|x| self.attention_norm.forward(x))
+            .collect();
+        let attended = self.attention.forward_masked(&normalized, mask);
+        let sequence: Vec<Vec<Val>> = sequence
+            .iter()
+            .zip(attended)
+            .map(|(x, a)| add(x, &a))
+            .collect();
+
+        sequence
+            .iter()
+            .map(|x| {
+                let mlp = self
+                    .output
+                    .forward(&self.hidden.forward(&self.mlp_norm.forward(x)));
+                add(x, &mlp)
+            })
+            .collect()
+    }
+
+    pub fn parameters(&self) -> Vec<Val> {
+        let mut params = self.attention_norm.parameters();
+        params.extend(self.attention.parameters());
+        params.extend(self.mlp_norm.parameters());
+        params.extend(self.hidden.parameters());
+        params.extend(self.output.parameters());
+        params
+    }
+}
+
+fn add(a: &[Val], b: &[Val]) -> Vec<Val> {
+    a.iter().zip(b).map(|(x, y)| x + y).collect()
+}
+
+/// A stack of [`TransformerBlock`]s followed by a final [`LayerNorm`].
+///
+/// ```
+/// use neuron::{transformer::Transformer, val::Val};
+///
+/// let model = Transformer::new(4, 16, 2).with_causal_mask();
+/// let sequence: Vec<Vec<Val>> = (0..3)
+///     .map(|p| (0..4).map(|f| Val::from((p + f) as f64)).collect())
+///     .collect();
+/// let outputs = model.forward(&sequence);
+/// assert_eq!((outputs.len(), outputs[0].len()), (3, 4));
+/// ```
+pub struct Transformer {
+    blocks: Vec<TransformerBlock>,
+    norm: LayerNorm,
+}
+
+impl Transformer {
+    /// `num_blocks` blocks over vectors of `dim` features, see [`TransformerBlock::new`].
+    pub fn new(dim: usize, hidden_size: usize, num_blocks: usize) -> Self {
+        Self {
+            blocks: (0..num_blocks)
+                .map(|_| TransformerBlock::new(dim, hidden_size))
+                .collect(),
+            norm: LayerNorm::new(dim),
+        }
+    }
+
+    /// Mask the attention of every block, see [`SelfAttention::with_causal_mask`].
+    pub fn with_causal_mask(mut self) -> Self {
+        self.blocks = self
+            .blocks
+            .into_iter()
+            .map(TransformerBlock::with_causal_mask)
+            .collect();
+        self
+    }
+
+    pub fn blocks(&self) -> &[TransformerBlock] {
+        &self.blocks
+    }
+
+    pub fn forward(&self, sequence: &[Vec<Val>]) -> Vec<Vec<Val>> {
+        self.forward_masked(sequence, &vec![true; sequence.len()])
+    }
+
+    /// See [`SelfAttention::forward_masked`].
+    pub fn forward_masked(&self, sequence: &[Vec<Val>], mask: &[bool]) -> Vec<Vec<Val>> {
+        let mut sequence = sequence.to_vec();
+        for block in &self.blocks {
+            sequence = block.forward_masked(&sequence, mask);
+        }
+        sequence.iter().map(|x| self.norm.forward(x)).collect()
+    }
+
+    pub fn parameters(&self) -> Vec<Val> {
+        let mut params: Vec<Val> = self.blocks.iter().flat_map(|b| b.parameters()).collect();
+        params.extend(self.norm.parameters());
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LayerNorm, SelfAttention, Transformer, TransformerBlock};
+    use crate::{testing::numerical_gradient, val::Val};
+
+    fn sequence(values: &[[f64; 3]]) -> Vec<Vec<Val>> {
+        values
+            .iter()
+            .map(|xs| xs.iter().map(|x| Val::from(*x)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn layer_norm() {
+        let norm = LayerNorm::new(4);
+        let xs: Vec<Val> = [1.0, 2.0, 3.0, 6.0].iter().map(|x| Val::from(*x)).collect();
+        let ys: Vec<f64> = norm.forward(&xs).iter().map(Val::data).collect();
+
+        let mean = ys.iter().sum::<f64>() / 4.0;
+        let variance = ys.iter().map(|y| (y - mean).powi(2)).sum::<f64>() / 4.0;
+        assert!(mean.abs() < 1e-12);
+        assert!((variance - 1.0).abs() < 1e-12);
+
+        // A constant vector is only centered, with finite gradients.
+        let xs: Vec<Val> = [2.0, 2.0].iter().map(|x| Val::new(*x, "x")).collect();
+        let ys = LayerNorm::new(2).forward(&xs);
+        assert!(ys.iter().all(|y| y.data() == 0.0));
+        ys.iter().cloned().sum::<Val>().back_prop_gradient();
+        assert!(xs.iter().all(|x| x.gradient().is_finite()));
+    }
+
+    #[test]
+    fn padding_mask() {
+        let attention = SelfAttention::new(3).with_causal_mask();
+        let padded = sequence(&[[0.1, 0.2, 0.3], [1.0, -1.0, 0.5], [0.0, 0.0, 0.0]]);
+        let mask = [true, false, true];
+
+        let weights = attention.attention_weights(&padded, &mask);
+        let weights: Vec<Vec<f64>> = weights
+            .iter()
+            .map(|w| w.iter().map(Val::data).collect())
+            .collect();
+        assert_eq!(weights[0], [1.0]);
+        assert_eq!(weights[1], [1.0, 0.0]);
+        assert_eq!(weights[2][1], 0.0);
+        assert!((weights[2][0] + weights[2][2] - 1.0).abs() < 1e-12);
+
+        // The padded position doesn't change the others.
+        let mut changed = padded.clone();
+        changed[1] = sequence(&[[5.0, 5.0, 5.0]]).remove(0);
+        let a = attention.forward_masked(&padded, &mask);
+        let b = attention.forward_masked(&changed, &mask);
+        for i in [0, 2] {
+            for (x, y) in a[i].iter().zip(&b[i]) {
+                assert_eq!(x.data(), y.data());
+            }
+        }
+
+        a.iter()
+            .flatten()
+            .cloned()
+            .sum::<Val>()
+            .back_prop_gradient();
+        assert!(attention
+            .parameters()
+            .iter()
+            .all(|p| p.gradient().is_finite()));
+    }
+
+    #[test]
+    fn causal_block() {
+        let block = TransformerBlock::new(3, 8).with_causal_mask();
+        let a = block.forward(&sequence(&[[0.1, 0.2, 0.3], [1.0, -1.0, 0.5]]));
+        let b = block.forward(&sequence(&[[0.1, 0.2, 0.3], [-2.0, 0.0, 4.0]]));
+
+        // The first position can't see the second one.
+        for (x, y) in a[0].iter().zip(&b[0]) {
+            assert_eq!(x.data(), y.data());
+        }
+        assert_ne!(a[1][0].data(), b[1][0].data());
+    }
+
+    #[test]
+    fn gradients() {
+        let model = Transformer::new(3, 8, 2);
+        // 2 blocks of 2 norms, 4 projections and a 2 layer MLP, and the final norm.
+        let per_block = 2 * 6 + 4 * 12 + (3 * 8 + 8) + (8 * 3 + 3);
+        assert_eq!(model.parameters().len(), 2 * per_block + 6);
+
+        let outputs = model.forward(&sequence(&[[0.1, 0.2, 0.3], [1.0, -1.0, 0.5]]));
+        let weights = [0.3, -0.7, 1.1];
+        let total: Val = outputs
+            .iter()
+            .flatten()
+            .zip(weights.iter().cycle())
+            .map(|(o, w)| o * *w)
+            .sum();
+        total.back_prop_gradient();
+
+        // A query weight of the first block, behind both blocks and every kind of node.
+        let param = &model.parameters()[6];
+        let expected = numerical_gradient(&total, param, 1e-6);
+        assert!((param.gradient() - expected).abs() < 1e-6);
+    }
+}
@@ -1,19 +1,31 @@
 //! This module borrows heavily from
 //! https://github.com/danielway/micrograd-rs/blob/master/src/value.rs
 use std::{
-    cell::{Ref, RefCell},
-    collections::HashSet,
+    cell::{Cell, Ref, RefCell},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fmt::Display,
     hash::Hash,
     ops::Deref,
     rc::Rc,
 };
 
+use crate::{audit, display, profiler, trace};
+
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct Val(Rc<RefCell<ValInternal>>);
 
 type PropagateGradientBackwardsFn = fn(value: &Ref<ValInternal>);
 
+/// Computes the data of a node from its parents.
+type ComputeForwardFn = fn(parents: &[Val]) -> f64;
+
+/// Identifies a node of the graph, two distinct nodes can hold the same values.
+type NodeId = *const RefCell<ValInternal>;
+
+thread_local! {
+    static MAX_DEPTH: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
 #[derive(Clone, Debug)]
 pub struct ValInternal {
     data: f64,
@@ -21,7 +33,9 @@ pub struct ValInternal {
     label: Option<String>,
     operation: Option<String>,
     parents: Vec<Val>,
+    forward: Option<ComputeForwardFn>,
     propagate: Option<PropagateGradientBackwardsFn>,
+    placeholder: bool,
 }
 
 impl Val {
@@ -32,11 +46,45 @@ impl Val {
             label: Some(label.to_string()),
             operation: None,
             parents: vec![],
+            forward: None,
             propagate: None,
+            placeholder: false,
         })
     }
 
+    /// An input node whose value is meant to be set with [`Val::set_data`] before every run of
+    /// the graph, instead of building a new graph per input. It holds NaN until then, so a
+    /// forgotten input shows up in the results.
+    pub fn placeholder(label: &str) -> Self {
+        let val = Self::new(f64::NAN, label);
+        val.borrow_mut().placeholder = true;
+        val
+    }
+
+    pub fn is_placeholder(&self) -> bool {
+        self.borrow().placeholder
+    }
+
+    /// The placeholders the graph ending at this node depends on.
+    pub fn placeholders(&self) -> Vec<Val> {
+        self.topological_order()
+            .into_iter()
+            .filter(Val::is_placeholder)
+            .collect()
+    }
+
     fn with_neuron_internal(value: ValInternal) -> Val {
+        if let Some(op) = &value.operation {
+            if audit::is_enabled() {
+                let inputs: Vec<f64> = value.parents.iter().map(Val::data).collect();
+                audit::record(op, value.label.as_deref(), &inputs, value.data);
+            }
+        }
+        if trace::is_enabled() {
+            let parents = value.parents.iter().map(Val::label).collect();
+            trace::record(value.operation.as_deref(), parents, value.data);
+        }
+
         Val(Rc::new(RefCell::new(value)))
     }
 
@@ -45,62 +93,996 @@ impl Val {
         self
     }
 
+    pub fn label(&self) -> Option<String> {
+        self.borrow().label.clone()
+    }
+
+    /// A new leaf with the data and label of this node, so gradients can't flow back through
+    /// it, e.g. the hidden state carried from one truncated back propagation window to the
+    /// next.
+    pub fn detach(&self) -> Val {
+        let node = self.borrow();
+        Val::with_neuron_internal(ValInternal::new(
+            node.data,
+            node.label.clone(),
+            None,
+            vec![],
+            None,
+            None,
+        ))
+    }
+
+    pub fn data(&self) -> f64 {
+        self.borrow().data
+    }
+
+    pub fn set_data(&self, data: f64) {
+        self.borrow_mut().data = data;
+    }
+
     pub fn gradient(&self) -> f64 {
         self.borrow().gradient
     }
 
+    pub fn set_gradient(&self, gradient: f64) {
+        self.borrow_mut().gradient = gradient;
+    }
+
     pub fn reset_gradient(&self) {
         self.borrow_mut().gradient = 0.0;
     }
 
+    /// Panics if the graph is deeper than the limit set by [`Val::set_max_depth`].
     pub fn back_prop_gradient(&self) {
+        let _scope = profiler::backward_pass();
+        if let Err(error) = self.check_max_depth() {
+            panic!("{error}, is a loop missing Val::accumulate or Val::detach?");
+        }
+        self.back_prop(&self.topological_order());
+    }
+
+    /// Make [`Val::back_prop_gradient`] panic and the checked runs of the graph, e.g.
+    /// [`Val::try_back_prop_gradient`], fail on graphs deeper than `max_depth` ops, on this
+    /// thread, instead of running slowly or overflowing the stack when they are dropped. `None`
+    /// removes the limit.
+    ///
+    /// Deep graphs usually come from summing in a loop with `+` instead of
+    /// [`Val::accumulate`], or from carrying state across iterations without [`Val::detach`].
+    pub fn set_max_depth(max_depth: Option<usize>) {
+        MAX_DEPTH.with(|m| m.set(max_depth));
+    }
+
+    /// The number of ops on the longest path from a leaf to this node, 0 for a leaf.
+    pub fn depth(&self) -> usize {
+        self.deepest_path().len() - 1
+    }
+
+    /// Fails with the labels of the deepest path if it has more than `max_depth` ops.
+    pub fn check_depth(&self, max_depth: usize) -> Result<(), GraphError> {
+        let path = self.deepest_path();
+        if path.len() - 1 <= max_depth {
+            return Ok(());
+        }
+
+        let leaf = path.last().unwrap().borrow();
+        Err(GraphError {
+            kind: GraphErrorKind::TooDeep,
+            label: leaf.label.clone(),
+            op: leaf.operation.clone(),
+            path: truncate_path(path.iter().map(Val::name).collect()),
+        })
+    }
+
+    fn check_max_depth(&self) -> Result<(), GraphError> {
+        match MAX_DEPTH.with(Cell::get) {
+            Some(max_depth) => self.check_depth(max_depth),
+            None => Ok(()),
+        }
+    }
+
+    /// The nodes of a longest path from this node down to a leaf.
+    fn deepest_path(&self) -> Vec<Val> {
+        // The depth of every node, and its parent on a longest path.
+        let mut deepest: HashMap<NodeId, (usize, Option<Val>)> = HashMap::new();
+        for node in self.topological_order() {
+            // Ties go to the first parent.
+            let parent = node
+                .borrow()
+                .parents
+                .iter()
+                .rev()
+                .max_by_key(|p| deepest[&Rc::as_ptr(p)].0)
+                .cloned();
+            let depth = parent.as_ref().map_or(0, |p| deepest[&Rc::as_ptr(p)].0 + 1);
+            deepest.insert(Rc::as_ptr(&node), (depth, parent));
+        }
+
+        let mut path = vec![self.clone()];
+        while let Some(parent) = deepest[&Rc::as_ptr(path.last().unwrap())].1.clone() {
+            path.push(parent);
+        }
+        path
+    }
+
+    /// Back propagate only through the part of the graph that can influence one of `params`.
+    ///
+    /// Branches of the graph that none of `params` feed into are skipped, so the gradients of
+    /// nodes in them are not computed.
+    pub fn back_prop_gradient_to(&self, params: &[Val]) {
+        let _scope = profiler::backward_pass();
+        self.back_prop(&self.pruned_order(params));
+    }
+
+    /// Accumulate the gradient of this node into `nodes` only.
+    ///
+    /// The gradients of every other node, e.g. the parameters of a model when only the gradient
+    /// of an input is needed, are left as they were.
+    pub fn backward_wrt(&self, nodes: &[Val]) {
+        let _scope = profiler::backward_pass();
+        let order = self.pruned_order(nodes);
+
+        let listed: HashSet<NodeId> = nodes.iter().map(|n| Rc::as_ptr(n)).collect();
+        let mut saved: Vec<(Val, f64)> = vec![];
+        let mut seen: HashSet<NodeId> = HashSet::new();
+
+        // The backward functions write into the parents of the nodes they run for.
+        let touched = std::iter::once(self.clone())
+            .chain(order.iter().flat_map(|n| n.borrow().parents.clone()))
+            .chain(order.iter().cloned());
+        for node in touched {
+            let ptr = Rc::as_ptr(&node);
+            if !listed.contains(&ptr) && seen.insert(ptr) {
+                saved.push((node.clone(), node.gradient()));
+                node.reset_gradient();
+            }
+        }
+
+        self.back_prop(&order);
+
+        for (node, gradient) in saved {
+            node.set_gradient(gradient);
+        }
+    }
+
+    /// Run the backward functions of `nodes`, which must be in topological order, children
+    /// first.
+    fn back_prop(&self, nodes: &[Val]) {
         self.borrow_mut().gradient = 1.0;
-        let mut visited: HashSet<Val> = HashSet::new();
-
-        fn back_prop_internal(node: &Val, visited: &mut HashSet<Val>) {
-            if !visited.contains(node) {
-                visited.insert(node.clone());
-                let borrowed = node.borrow();
-                if let Some(f) = borrowed.propagate {
-                    f(&borrowed);
+
+        for node in nodes.iter().rev() {
+            let borrowed = node.borrow();
+            if let Some(f) = borrowed.propagate {
+                let _scope = profiler::backward(borrowed.operation.as_deref().unwrap_or(""));
+                f(&borrowed);
+            }
+        }
+    }
+
+    /// The nodes of [`Val::topological_order`] that have one of `params` among their ancestors
+    /// (or are one of them), i.e. the only nodes gradient has to flow through to reach `params`.
+    pub(crate) fn pruned_order(&self, params: &[Val]) -> Vec<Val> {
+        let mut reaching: HashSet<NodeId> = params.iter().map(|p| Rc::as_ptr(p)).collect();
+
+        self.topological_order()
+            .into_iter()
+            .filter(|node| {
+                let ptr = Rc::as_ptr(node);
+                let reaches = reaching.contains(&ptr)
+                    || node
+                        .borrow()
+                        .parents
+                        .iter()
+                        .any(|p| reaching.contains(&Rc::as_ptr(p)));
+
+                if reaches {
+                    reaching.insert(ptr);
                 }
+                reaches
+            })
+            .collect()
+    }
 
+    /// Every node of the graph ending at this node exactly once, parents before children.
+    pub(crate) fn topological_order(&self) -> Vec<Val> {
+        let mut order = vec![];
+        let mut visited: HashSet<NodeId> = HashSet::new();
+
+        // The flag marks nodes whose parents have already been pushed.
+        let mut stack = vec![(self.clone(), false)];
+        while let Some((node, expanded)) = stack.pop() {
+            if expanded {
+                order.push(node);
+            } else if visited.insert(Rc::as_ptr(&node)) {
+                stack.push((node.clone(), true));
                 for parent in &node.borrow().parents {
-                    back_prop_internal(parent, visited);
+                    if !visited.contains(&Rc::as_ptr(parent)) {
+                        stack.push((parent.clone(), false));
+                    }
+                }
+            }
+        }
+
+        order
+    }
+
+    /// Re-evaluate the data of every node of the graph ending at this node from the current
+    /// data of its leaves, e.g. after an optimizer step or after setting placeholders, without
+    /// building a new graph. Gradients are left as they are.
+    pub fn recompute(&self) {
+        for node in self.topological_order() {
+            let data = {
+                let node = node.borrow();
+                node.forward.map(|f| f(&node.parents))
+            };
+
+            if let Some(data) = data {
+                node.set_data(data);
+            }
+        }
+    }
+
+    /// Like [`Val::recompute`] but stops at the first node that can't be evaluated: a
+    /// placeholder without a value, a node borrowed elsewhere, or the first node producing NaN
+    /// out of non-NaN operands. Also fails on graphs deeper than [`Val::set_max_depth`].
+    pub fn try_recompute(&self) -> Result<(), GraphError> {
+        self.check_max_depth()?;
+        for node in self.topological_order() {
+            let Ok(mut internal) = node.try_borrow_mut() else {
+                return Err(self.graph_error(&node, GraphErrorKind::BorrowConflict));
+            };
+            if internal.placeholder && internal.data.is_nan() {
+                drop(internal);
+                return Err(self.graph_error(&node, GraphErrorKind::MissingPlaceholder));
+            }
+
+            if let Some(forward) = internal.forward {
+                let data = forward(&internal.parents);
+                internal.data = data;
+                let nan_inputs = internal.parents.iter().any(|p| p.data().is_nan());
+                if data.is_nan() && !nan_inputs {
+                    drop(internal);
+                    return Err(self.graph_error(&node, GraphErrorKind::NaN));
                 }
             }
         }
 
-        back_prop_internal(self, &mut visited);
+        Ok(())
     }
 
+    /// Like [`Val::back_prop_gradient`] but checks the graph first for its depth, placeholders
+    /// without a value and nodes borrowed elsewhere, and stops at the first node sending NaN
+    /// gradients back from a non-NaN gradient.
+    pub fn try_back_prop_gradient(&self) -> Result<(), GraphError> {
+        let _scope = profiler::backward_pass();
+        self.check_max_depth()?;
+        let order = self.topological_order();
+
+        for node in &order {
+            let Ok(internal) = node.try_borrow_mut() else {
+                return Err(self.graph_error(node, GraphErrorKind::BorrowConflict));
+            };
+            if internal.placeholder && internal.data.is_nan() {
+                drop(internal);
+                return Err(self.graph_error(node, GraphErrorKind::MissingPlaceholder));
+            }
+        }
+
+        self.borrow_mut().gradient = 1.0;
+        for node in order.iter().rev() {
+            let borrowed = node.borrow();
+            let Some(f) = borrowed.propagate else {
+                continue;
+            };
+
+            let _scope = profiler::backward(borrowed.operation.as_deref().unwrap_or(""));
+            f(&borrowed);
+            let nan_parent = borrowed.parents.iter().any(|p| p.gradient().is_nan());
+            if nan_parent && !borrowed.gradient.is_nan() {
+                drop(borrowed);
+                return Err(self.graph_error(node, GraphErrorKind::NaN));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The label of a node, or its op, or `const`.
+    fn name(&self) -> String {
+        let node = self.borrow();
+        node.label
+            .clone()
+            .or_else(|| node.operation.clone())
+            .unwrap_or_else(|| "const".to_string())
+    }
+
+    fn graph_error(&self, node: &Val, kind: GraphErrorKind) -> GraphError {
+        // Breadth first from this node, so the path found is a shortest one.
+        let target = Rc::as_ptr(node);
+        let mut children: HashMap<NodeId, Val> = HashMap::new();
+        let mut queue = VecDeque::from([self.clone()]);
+        let mut seen: HashSet<NodeId> = HashSet::from([Rc::as_ptr(self)]);
+        while let Some(current) = queue.pop_front() {
+            if Rc::as_ptr(&current) == target {
+                break;
+            }
+            for parent in &current.borrow().parents {
+                if seen.insert(Rc::as_ptr(parent)) {
+                    children.insert(Rc::as_ptr(parent), current.clone());
+                    queue.push_back(parent.clone());
+                }
+            }
+        }
+
+        let mut path = vec![node.name()];
+        let mut current = target;
+        while let Some(child) = children.get(&current) {
+            path.push(child.name());
+            current = Rc::as_ptr(child);
+        }
+        path.reverse();
+
+        let internal = node.borrow();
+        GraphError {
+            kind,
+            label: internal.label.clone(),
+            op: internal.operation.clone(),
+            path: truncate_path(path),
+        }
+    }
+
+    /// Cut every node of the graph ending at this node off from its parents, keeping its data,
+    /// gradient, label and op.
+    ///
+    /// A node keeps its whole history alive through its parents, so holding on to e.g. the loss
+    /// of every iteration keeps every graph ever built in memory. After this call the nodes only
+    /// hold themselves and can no longer back propagate.
+    pub fn free_graph(&self) {
+        // Parents come first, so each node dropped here is still held by `nodes` and releasing
+        // a long chain doesn't recurse.
+        let nodes = self.topological_order();
+        for node in &nodes {
+            let mut node = node.borrow_mut();
+            node.parents = vec![];
+            node.forward = None;
+            node.propagate = None;
+        }
+    }
+
+    /// How many nodes of each op the graph ending at this node holds, leaves excluded.
+    pub fn op_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for node in self.topological_order() {
+            if let Some(op) = &node.borrow().operation {
+                *counts.entry(op.clone()).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    /// A rough count of the floating point operations needed to evaluate the graph ending at
+    /// this node: one per op node, except n-ary sums which take one per extra operand.
+    pub fn flops(&self) -> usize {
+        self.topological_order()
+            .iter()
+            .map(|node| {
+                let node = node.borrow();
+                match node.operation.as_deref() {
+                    None => 0,
+                    Some("Σ") => node.parents.len().saturating_sub(1),
+                    Some(_) => 1,
+                }
+            })
+            .sum()
+    }
+
+    /// A rough estimate of the memory held by the graph ending at this node.
+    pub fn memory_estimate(&self) -> GraphMemory {
+        let nodes = self.topological_order();
+
+        let bytes = nodes
+            .iter()
+            .map(|node| {
+                let node = node.borrow();
+                // The strong and weak counts of the Rc live next to the value.
+                2 * size_of::<usize>()
+                    + size_of::<RefCell<ValInternal>>()
+                    + node.label.as_ref().map_or(0, String::capacity)
+                    + node.operation.as_ref().map_or(0, String::capacity)
+                    + node.parents.capacity() * size_of::<Val>()
+            })
+            .sum();
+
+        GraphMemory {
+            nodes: nodes.len(),
+            bytes,
+        }
+    }
+
+    /// Describe the graph ending at this node as JSON, for tools that do not link this crate.
+    ///
+    /// ```text
+    /// {
+    ///   "output": 2,
+    ///   "nodes": [
+    ///     {"id": 0, "label": "a", "op": null, "data": 2.0, "grad": -3.0},
+    ///     ...
+    ///   ],
+    ///   "edges": [{"from": 0, "to": 2, "position": 0}, ...]
+    /// }
+    /// ```
+    ///
+    /// Node ids are indices into `nodes`, which lists parents before their children, and
+    /// `output` is the id of this node. An edge goes from a parent to the node it feeds into,
+    /// `position` is the index of the parent among the operands of the op, e.g. 1 for the
+    /// exponent of `^`. Missing labels and ops, as well as non-finite numbers, are `null`.
+    pub fn to_json_graph(&self) -> String {
+        fn string(s: &Option<String>) -> String {
+            let Some(s) = s else {
+                return "null".to_string();
+            };
+
+            let mut escaped = String::from('"');
+            for c in s.chars() {
+                match c {
+                    '"' => escaped.push_str("\\\""),
+                    '\\' => escaped.push_str("\\\\"),
+                    c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => escaped.push(c),
+                }
+            }
+            escaped.push('"');
+            escaped
+        }
+
+        fn number(n: f64) -> String {
+            if n.is_finite() {
+                format!("{n:?}")
+            } else {
+                "null".to_string()
+            }
+        }
+
+        let nodes = self.topological_order();
+        let ids: HashMap<NodeId, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(id, node)| (Rc::as_ptr(node), id))
+            .collect();
+
+        let mut json_nodes = vec![];
+        let mut json_edges = vec![];
+        for (id, node) in nodes.iter().enumerate() {
+            let node = node.borrow();
+            json_nodes.push(format!(
+                "{{\"id\":{id},\"label\":{},\"op\":{},\"data\":{},\"grad\":{}}}",
+                string(&node.label),
+                string(&node.operation),
+                number(node.data),
+                number(node.gradient)
+            ));
+
+            for (position, parent) in node.parents.iter().enumerate() {
+                json_edges.push(format!(
+                    "{{\"from\":{},\"to\":{id},\"position\":{position}}}",
+                    ids[&Rc::as_ptr(parent)]
+                ));
+            }
+        }
+
+        format!(
+            "{{\"output\":{},\"nodes\":[{}],\"edges\":[{}]}}",
+            nodes.len() - 1,
+            json_nodes.join(","),
+            json_edges.join(",")
+        )
+    }
+
+    /// The expression ending at this node as an indented tree, one node per line with its op,
+    /// label, data and gradient, operands below their op. Shared nodes are repeated under
+    /// every op using them, and operands deeper than `max_depth` are elided as `...`.
+    pub fn tree(&self, max_depth: usize) -> String {
+        let mut out = String::new();
+
+        // `None` stands for the elided operands of the node above.
+        let mut stack = vec![(Some(self.clone()), 0)];
+        while let Some((node, depth)) = stack.pop() {
+            let indent = "  ".repeat(depth);
+            let Some(node) = node else {
+                out.push_str(&format!("{indent}...\n"));
+                continue;
+            };
+
+            let node = node.borrow();
+            let name = [
+                node.operation.as_deref().map(display::op),
+                node.label.clone(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+            out.push_str(&format!(
+                "{indent}{} (data: {}, grad: {})\n",
+                if name.is_empty() { "const" } else { &name },
+                display::number(node.data, Some(4)),
+                display::number(node.gradient, Some(4))
+            ));
+
+            if depth == max_depth && !node.parents.is_empty() {
+                stack.push((None, depth + 1));
+            } else if depth < max_depth {
+                for parent in node.parents.iter().rev() {
+                    stack.push((Some(parent.clone()), depth + 1));
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Print [`Val::tree`], a terminal friendly alternative to the notebook only `visualize`.
+    pub fn print_tree(&self, max_depth: usize) {
+        print!("{}", self.tree(max_depth));
+    }
+
+    /// A small SVG drawing of the graph ending at this node, `width` by `height` pixels, for
+    /// thumbnails: leaves on the left, every node one column right of its deepest parent, op
+    /// nodes in blue. Hovering a node shows its label or op.
+    pub fn to_svg(&self, width: usize, height: usize) -> String {
+        const MARGIN: f64 = 8.0;
+
+        let nodes = self.topological_order();
+        let mut depths: HashMap<NodeId, usize> = HashMap::new();
+        let mut columns: Vec<Vec<usize>> = vec![];
+        let mut positions = vec![];
+        for node in &nodes {
+            let depth = node
+                .borrow()
+                .parents
+                .iter()
+                .map(|p| depths[&Rc::as_ptr(p)] + 1)
+                .max()
+                .unwrap_or(0);
+            depths.insert(Rc::as_ptr(node), depth);
+            if columns.len() <= depth {
+                columns.push(vec![]);
+            }
+            positions.push((depth, columns[depth].len()));
+            columns[depth].push(positions.len() - 1);
+        }
+
+        let span = |size: usize, count: usize, i: usize| {
+            let usable = size as f64 - 2.0 * MARGIN;
+            MARGIN + usable * (i as f64 + 0.5) / count as f64
+        };
+        let center = |(depth, row): (usize, usize)| {
+            (
+                span(width, columns.len(), depth),
+                span(height, columns[depth].len(), row),
+            )
+        };
+        let ids: HashMap<NodeId, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(id, node)| (Rc::as_ptr(node), id))
+            .collect();
+
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\">\n"
+        );
+        for (id, node) in nodes.iter().enumerate() {
+            let (x, y) = center(positions[id]);
+            for parent in &node.borrow().parents {
+                let (px, py) = center(positions[ids[&Rc::as_ptr(parent)]]);
+                out.push_str(&format!(
+                    "<line x1=\"{px:.1}\" y1=\"{py:.1}\" x2=\"{x:.1}\" y2=\"{y:.1}\" \
+                     stroke=\"lightgray\"/>\n"
+                ));
+            }
+        }
+        for (id, node) in nodes.iter().enumerate() {
+            let (x, y) = center(positions[id]);
+            let node = node.borrow();
+            let fill = if node.operation.is_some() {
+                "steelblue"
+            } else {
+                "gray"
+            };
+            let title = node
+                .label
+                .clone()
+                .or(node.operation.as_deref().map(display::op))
+                .map_or(String::new(), |t| {
+                    t.replace('&', "&amp;")
+                        .replace('<', "&lt;")
+                        .replace('>', "&gt;")
+                });
+            out.push_str(&format!(
+                "<circle cx=\"{x:.1}\" cy=\"{y:.1}\" r=\"3\" fill=\"{fill}\">\
+                 <title>{title}</title></circle>\n"
+            ));
+        }
+
+        out.push_str("</svg>\n");
+        out
+    }
+
+    /// `x^n`, differentiable with respect to both `x` and `n`, e.g. for a learned exponent. The
+    /// gradient `x^n ln x` of the exponent is only defined for positive bases, and taken to be 0
+    /// otherwise.
     pub fn pow(&self, other: &Val) -> Val {
-        let result = self.borrow().data.powf(other.borrow().data);
+        let _scope = profiler::forward("^");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data().powf(parents[1].data());
+        let parents = vec![self.clone(), other.clone()];
 
+        // x^n is the data of this node. The parents are borrowed one at a time, as they can be
+        // the same node.
         let prop_fn: PropagateGradientBackwardsFn = |value| {
-            let mut base = value.parents[0].borrow_mut();
-            let power = value.parents[1].borrow();
+            let x = value.parents[0].data();
+            let n = value.parents[1].data();
 
             // d(x^(n))/dx = n . x^ (n-1)
-            base.gradient += power.data * (base.data.powf(power.data - 1.0)) * value.gradient;
+            value.parents[0].borrow_mut().gradient += n * x.powf(n - 1.0) * value.gradient;
+
+            // d(x^(n))/dn = x^n . ln x
+            if x > 0.0 {
+                value.parents[1].borrow_mut().gradient += value.data * x.ln() * value.gradient;
+            }
         };
 
         Val::with_neuron_internal(ValInternal::new(
-            result,
+            forward_fn(&parents),
             None,
             Some("^".to_string()),
-            vec![self.clone(), other.clone()],
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// `x^n` for an integer `n`, defined for negative bases unlike [`Val::pow`] with a
+    /// fractional exponent, with the exact gradient `n * x^(n-1)`. The exponent is kept as a
+    /// constant operand so the graph can be re-evaluated.
+    pub fn powi(&self, n: i32) -> Val {
+        let _scope = profiler::forward("powi");
+        let forward_fn: ComputeForwardFn =
+            |parents| parents[0].data().powi(parents[1].data() as i32);
+        let parents = vec![self.clone(), Val::from(n as f64)];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let n = value.parents[1].data() as i32;
+            let mut base = value.parents[0].borrow_mut();
+
+            // x^0 is constant, even at 0 where x^-1 is infinite.
+            if n != 0 {
+                base.gradient += n as f64 * base.data.powi(n - 1) * value.gradient;
+            }
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("powi".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// `self / other` with denominators closer to zero than `eps` replaced by `eps` (keeping
+    /// their sign, zero counting as positive), so neither the result nor the gradients can
+    /// blow up. No gradient flows to a clamped denominator.
+    pub fn div_eps(&self, other: &Val, eps: f64) -> Val {
+        let _scope = profiler::forward("/");
+        assert!(eps > 0.0, "eps must be positive");
+
+        fn denominator(d: f64, eps: f64) -> f64 {
+            if d.abs() >= eps {
+                d
+            } else if d < 0.0 {
+                -eps
+            } else {
+                eps
+            }
+        }
+
+        let forward_fn: ComputeForwardFn =
+            |parents| parents[0].data() / denominator(parents[1].data(), parents[2].data());
+        let parents = vec![self.clone(), other.clone(), Val::from(eps)];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let eps = value.parents[2].data();
+            let (x, d) = (value.parents[0].data(), value.parents[1].data());
+            let clamped = d.abs() < eps;
+            let d = denominator(d, eps);
+
+            value.parents[0].borrow_mut().gradient += value.gradient / d;
+            if !clamped {
+                value.parents[1].borrow_mut().gradient -= x / (d * d) * value.gradient;
+            }
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("/".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// The larger of `self` and `other`. The gradient flows to the larger operand only, to
+    /// `self` on a tie.
+    pub fn max(&self, other: &Val) -> Val {
+        let _scope = profiler::forward("max");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data().max(parents[1].data());
+        let parents = vec![self.clone(), other.clone()];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let first_wins = value.parents[0].data() >= value.parents[1].data();
+            let winner = if first_wins { 0 } else { 1 };
+            value.parents[winner].borrow_mut().gradient += value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("max".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// The smaller of `self` and `other`. The gradient flows to the smaller operand only, to
+    /// `self` on a tie.
+    pub fn min(&self, other: &Val) -> Val {
+        let _scope = profiler::forward("min");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data().min(parents[1].data());
+        let parents = vec![self.clone(), other.clone()];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let first_wins = value.parents[0].data() <= value.parents[1].data();
+            let winner = if first_wins { 0 } else { 1 };
+            value.parents[winner].borrow_mut().gradient += value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("min".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// `self` limited to `[lo, hi]`. The gradient only flows where `self` is within the bounds,
+    /// the bounds are kept as constant operands so the graph can be re-evaluated.
+    pub fn clamp(&self, lo: f64, hi: f64) -> Val {
+        let _scope = profiler::forward("clamp");
+        assert!(lo <= hi, "lo must not be greater than hi");
+
+        let forward_fn: ComputeForwardFn = |parents| {
+            parents[0]
+                .data()
+                .clamp(parents[1].data(), parents[2].data())
+        };
+        let parents = vec![self.clone(), Val::from(lo), Val::from(hi)];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let (lo, hi) = (value.parents[1].data(), value.parents[2].data());
+            let mut first = value.parents[0].borrow_mut();
+            if (lo..=hi).contains(&first.data) {
+                first.gradient += value.gradient;
+            }
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("clamp".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// `x` limited to `[-1, 1]`, a piecewise linear [`Val::tanh`] with the gradient 1 within
+    /// the bounds and 0 outside.
+    pub fn hard_tanh(&self) -> Val {
+        let _scope = profiler::forward("HardTanh");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data().clamp(-1.0, 1.0);
+        let parents = vec![self.clone()];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            if (-1.0..=1.0).contains(&first.data) {
+                first.gradient += value.gradient;
+            }
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("HardTanh".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// `x / 6 + 1 / 2` limited to `[0, 1]`, a piecewise linear [`Val::sigmoid`] with the
+    /// gradient 1/6 for `x` in `[-3, 3]` and 0 outside.
+    pub fn hard_sigmoid(&self) -> Val {
+        let _scope = profiler::forward("HardSigmoid");
+        let forward_fn: ComputeForwardFn =
+            |parents| (parents[0].data() / 6.0 + 0.5).clamp(0.0, 1.0);
+        let parents = vec![self.clone()];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            if (-3.0..=3.0).contains(&first.data) {
+                first.gradient += value.gradient / 6.0;
+            }
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("HardSigmoid".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// Add `other` to this node in place: `acc.accumulate(x)` has the value of `acc + x`.
+    ///
+    /// Summing many terms with `+` builds a chain as deep as the number of terms. The first
+    /// call here replaces this node with a sum node over both operands, later calls add their
+    /// operand to it, keeping the graph one level deep. A sum node that is shared with other
+    /// nodes is never modified, a new one is started over it instead.
+    pub fn accumulate(&mut self, other: Val) {
+        const OP: &str = "Σ";
+
+        let extendable =
+            Rc::strong_count(&self.0) == 1 && self.borrow().operation.as_deref() == Some(OP);
+        if extendable {
+            let mut node = self.borrow_mut();
+            node.data += other.data();
+            node.parents.push(other);
+            return;
+        }
+
+        let _scope = profiler::forward(OP);
+        let forward_fn: ComputeForwardFn = |parents| parents.iter().map(Val::data).sum();
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            // A node can be added more than once, only borrow one parent at a time.
+            for parent in &value.parents {
+                parent.borrow_mut().gradient += value.gradient;
+            }
+        };
+        let parents = vec![self.clone(), other];
+
+        *self = Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some(OP.to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ));
+    }
+
+    /// `sqrt(Σ x²)` as a single node over `vals`, see [`crate::ops::l2_norm`].
+    pub(crate) fn l2_norm(vals: &[Val]) -> Val {
+        let _scope = profiler::forward("l2_norm");
+        let forward_fn: ComputeForwardFn =
+            |parents| parents.iter().map(|p| p.data().powi(2)).sum::<f64>().sqrt();
+        let parents = vals.to_vec();
+
+        // d/dx_i ||x|| = x_i / ||x||, with the subgradient 0 at the zero vector. A node can be
+        // listed more than once, only borrow one parent at a time.
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            if value.data == 0.0 {
+                return;
+            }
+            for parent in &value.parents {
+                let x = parent.data();
+                parent.borrow_mut().gradient += x / value.data * value.gradient;
+            }
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("l2_norm".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// The absolute value, with the subgradient 0 at 0.
+    pub fn abs(&self) -> Val {
+        let _scope = profiler::forward("abs");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data().abs();
+        let parents = vec![self.clone()];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            let sign = if first.data == 0.0 {
+                0.0
+            } else {
+                first.data.signum()
+            };
+            first.gradient += sign * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("abs".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// -1, 0 or 1 as `x` is negative, zero or positive. Its gradient is 0 wherever it is
+    /// defined, see [`Val::sign_straight_through`] to train through it.
+    pub fn sign(&self) -> Val {
+        let _scope = profiler::forward("sign");
+        let forward_fn: ComputeForwardFn = |parents| sign(parents[0].data());
+        let parents = vec![self.clone()];
+
+        // No gradient flows back, as if the result was a constant.
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("sign".to_string()),
+            parents,
+            Some(forward_fn),
+            None,
+        ))
+    }
+
+    /// [`Val::sign`] with the straight-through estimator as its gradient: the gradient is
+    /// passed back unchanged, as if the op was the identity, so binarized weights or
+    /// activations can be trained.
+    pub fn sign_straight_through(&self) -> Val {
+        let _scope = profiler::forward("sign_ste");
+        let forward_fn: ComputeForwardFn = |parents| sign(parents[0].data());
+        let parents = vec![self.clone()];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            first.gradient += value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("sign_ste".to_string()),
+            parents,
+            Some(forward_fn),
             Some(prop_fn),
         ))
     }
 
     pub fn relu(&self) -> Val {
+        let _scope = profiler::forward("ReLU");
         // If the value is positive, leave it as it is, if it is negative, reset it to zero.
-        let result = if self.borrow().data < 0.0 {
-            0.0
-        } else {
-            self.borrow().data
+        let forward_fn: ComputeForwardFn = |parents| {
+            if parents[0].data() < 0.0 {
+                0.0
+            } else {
+                parents[0].data()
+            }
         };
+        let parents = vec![self.clone()];
 
         let prop_fn: PropagateGradientBackwardsFn = |value| {
             let mut first = value.parents[0].borrow_mut();
@@ -113,10 +1095,369 @@ impl Val {
         };
 
         Val::with_neuron_internal(ValInternal::new(
-            result,
+            forward_fn(&parents),
             None,
             Some("ReLU".to_string()),
-            vec![self.clone()],
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// Like [`Val::relu`] but with negative inputs scaled by `alpha` instead of zeroed, so they
+    /// still get a gradient.
+    pub fn leaky_relu(&self, alpha: f64) -> Val {
+        let _scope = profiler::forward("LeakyReLU");
+        let forward_fn: ComputeForwardFn = |parents| {
+            let x = parents[0].data();
+            if x < 0.0 {
+                parents[1].data() * x
+            } else {
+                x
+            }
+        };
+        let parents = vec![self.clone(), Val::from(alpha)];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let alpha = value.parents[1].data();
+            let mut first = value.parents[0].borrow_mut();
+            let slope = if first.data > 0.0 { 1.0 } else { alpha };
+            first.gradient += slope * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("LeakyReLU".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// Like [`Val::leaky_relu`] with the slope of negative inputs a node, so it can be learned.
+    pub fn prelu(&self, slope: &Val) -> Val {
+        let _scope = profiler::forward("PReLU");
+        let forward_fn: ComputeForwardFn = |parents| {
+            let x = parents[0].data();
+            if x < 0.0 {
+                parents[1].data() * x
+            } else {
+                x
+            }
+        };
+        let parents = vec![self.clone(), slope.clone()];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let (x, slope) = (value.parents[0].data(), value.parents[1].data());
+            // The input and the slope may be the same node, only borrow one at a time.
+            if x > 0.0 {
+                value.parents[0].borrow_mut().gradient += value.gradient;
+            } else {
+                value.parents[0].borrow_mut().gradient += slope * value.gradient;
+                value.parents[1].borrow_mut().gradient += x * value.gradient;
+            }
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("PReLU".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// The exponential linear unit: `x` for positive inputs and `alpha * (e^x - 1)` otherwise,
+    /// smooth at 0 when `alpha` is 1.
+    pub fn elu(&self, alpha: f64) -> Val {
+        let _scope = profiler::forward("ELU");
+        let forward_fn: ComputeForwardFn = |parents| {
+            let x = parents[0].data();
+            if x > 0.0 {
+                x
+            } else {
+                parents[1].data() * x.exp_m1()
+            }
+        };
+        let parents = vec![self.clone(), Val::from(alpha)];
+
+        // For negative inputs d/dx alpha * (e^x - 1) = alpha * e^x, the data of this node plus
+        // alpha.
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let alpha = value.parents[1].data();
+            let mut first = value.parents[0].borrow_mut();
+            let slope = if first.data > 0.0 {
+                1.0
+            } else {
+                value.data + alpha
+            };
+            first.gradient += slope * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("ELU".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// The Gaussian error linear unit `x * Φ(x)`, with the tanh approximation
+    /// `0.5 * x * (1 + tanh(sqrt(2 / π) * (x + 0.044715 * x^3)))`.
+    pub fn gelu(&self) -> Val {
+        let _scope = profiler::forward("GELU");
+        let forward_fn: ComputeForwardFn = |parents| gelu(parents[0].data());
+        let parents = vec![self.clone()];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            let x = first.data;
+            let t = gelu_inner(x).tanh();
+            let d_inner = (2.0 / std::f64::consts::PI).sqrt() * (1.0 + 3.0 * GELU_C * x.powi(2));
+            first.gradient +=
+                (0.5 * (1.0 + t) + 0.5 * x * (1.0 - t * t) * d_inner) * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("GELU".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// The sigmoid linear unit, or swish, `x * sigmoid(x)`.
+    pub fn silu(&self) -> Val {
+        let _scope = profiler::forward("SiLU");
+        let forward_fn: ComputeForwardFn = |parents| {
+            let x = parents[0].data();
+            x * sigmoid(x)
+        };
+        let parents = vec![self.clone()];
+
+        // d/dx x σ(x) = σ(x) + x σ(x) (1 - σ(x))
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            let s = sigmoid(first.data);
+            first.gradient += (s + first.data * s * (1.0 - s)) * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("SiLU".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// Mish, `x * tanh(softplus(x))` with `softplus(x) = ln(1 + e^x)`.
+    pub fn mish(&self) -> Val {
+        let _scope = profiler::forward("Mish");
+        let forward_fn: ComputeForwardFn = |parents| {
+            let x = parents[0].data();
+            x * tanh_softplus(x)
+        };
+        let parents = vec![self.clone()];
+
+        // d/dx x tanh(sp(x)) = tanh(sp(x)) + x (1 - tanh²(sp(x))) σ(x), as sp'(x) = σ(x).
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            let x = first.data;
+            let t = tanh_softplus(x);
+            let sigmoid = 0.5 * (1.0 + (0.5 * x).tanh());
+            first.gradient += (t + x * (1.0 - t * t) * sigmoid) * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("Mish".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    pub fn exp(&self) -> Val {
+        let _scope = profiler::forward("exp");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data().exp();
+        let parents = vec![self.clone()];
+
+        // d/dx e^x = e^x, the data of this node.
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            first.gradient += value.data * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("exp".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// The natural logarithm, NaN for negative values.
+    pub fn ln(&self) -> Val {
+        let _scope = profiler::forward("ln");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data().ln();
+        let parents = vec![self.clone()];
+
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            first.gradient += value.gradient / first.data;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("ln".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// The logarithm in a constant `base`, as `ln(x) / ln(base)`.
+    pub fn log(&self, base: f64) -> Val {
+        self.ln() * Val::from(1.0 / base.ln())
+    }
+
+    /// The square root, NaN for negative inputs. The gradient `0.5 / sqrt(x)` is infinite at 0.
+    pub fn sqrt(&self) -> Val {
+        let _scope = profiler::forward("sqrt");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data().sqrt();
+        let parents = vec![self.clone()];
+
+        // sqrt(x) is the data of this node.
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            first.gradient += 0.5 / value.data * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("sqrt".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// `1 / x`, a single node unlike `x.pow(&Val::from(-1.0))`.
+    pub fn recip(&self) -> Val {
+        let _scope = profiler::forward("recip");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data().recip();
+        let parents = vec![self.clone()];
+
+        // d/dx 1/x = -1/x^2, and 1/x is the data of this node.
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            first.gradient -= value.data * value.data * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("recip".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    /// The logistic function 1 / (1 + e^-x), squashing values into (0, 1).
+    pub fn sigmoid(&self) -> Val {
+        let _scope = profiler::forward("sigmoid");
+        let forward_fn: ComputeForwardFn = |parents| sigmoid(parents[0].data());
+        let parents = vec![self.clone()];
+
+        // d/dx σ(x) = σ(x)(1 - σ(x)), and σ(x) is the data of this node.
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            first.gradient += value.data * (1.0 - value.data) * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("sigmoid".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    pub fn tanh(&self) -> Val {
+        let _scope = profiler::forward("tanh");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data().tanh();
+        let parents = vec![self.clone()];
+
+        // d/dx tanh(x) = 1 - tanh(x)^2, and tanh(x) is the data of this node.
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            first.gradient += (1.0 - value.data * value.data) * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("tanh".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    pub fn sin(&self) -> Val {
+        let _scope = profiler::forward("sin");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data().sin();
+        let parents = vec![self.clone()];
+
+        // d/dx sin(x) = cos(x)
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            first.gradient += first.data.cos() * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("sin".to_string()),
+            parents,
+            Some(forward_fn),
+            Some(prop_fn),
+        ))
+    }
+
+    pub fn cos(&self) -> Val {
+        let _scope = profiler::forward("cos");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data().cos();
+        let parents = vec![self.clone()];
+
+        // d/dx cos(x) = -sin(x)
+        let prop_fn: PropagateGradientBackwardsFn = |value| {
+            let mut first = value.parents[0].borrow_mut();
+            first.gradient -= first.data.sin() * value.gradient;
+        };
+
+        Val::with_neuron_internal(ValInternal::new(
+            forward_fn(&parents),
+            None,
+            Some("cos".to_string()),
+            parents,
+            Some(forward_fn),
             Some(prop_fn),
         ))
     }
@@ -136,23 +1477,127 @@ impl Val {
 
                 g.add_edge(parent_idx, node_idx, String::new());
 
-                traverse(parent, parent_idx, g);
-            }
-        }
+                traverse(parent, parent_idx, g);
+            }
+        }
+
+        let node_idx = g.add_node(self.to_string());
+        traverse(self, node_idx, &mut g);
+
+        draw_graph(&g);
+    }
+}
+
+/// See [`Val::memory_estimate`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GraphMemory {
+    pub nodes: usize,
+    pub bytes: usize,
+}
+
+impl Display for GraphMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} nodes, ~{:.1} KiB",
+            self.nodes,
+            self.bytes as f64 / 1024.0
+        )
+    }
+}
+
+/// `1 / (1 + e^-x)`, only ever exponentiating negative numbers so large inputs can't overflow.
+pub(crate) fn sigmoid(x: f64) -> f64 {
+    if x >= 0.0 {
+        1.0 / (1.0 + (-x).exp())
+    } else {
+        x.exp() / (1.0 + x.exp())
+    }
+}
+
+const GELU_C: f64 = 0.044715;
+
+fn gelu_inner(x: f64) -> f64 {
+    (2.0 / std::f64::consts::PI).sqrt() * (x + GELU_C * x.powi(3))
+}
+
+/// See [`Val::gelu`].
+pub(crate) fn gelu(x: f64) -> f64 {
+    0.5 * x * (1.0 + gelu_inner(x).tanh())
+}
+
+/// `tanh(softplus(x))`, with the softplus computed so large inputs can't overflow.
+pub(crate) fn tanh_softplus(x: f64) -> f64 {
+    (x.max(0.0) + (-x.abs()).exp().ln_1p()).tanh()
+}
+
+/// The sign of `x`, 0 for 0 unlike [`f64::signum`].
+fn sign(x: f64) -> f64 {
+    if x == 0.0 {
+        0.0
+    } else {
+        x.signum()
+    }
+}
+
+/// Keep only the last few names of a path through the graph.
+fn truncate_path(mut path: Vec<String>) -> Vec<String> {
+    const MAX_PATH: usize = 6;
+
+    if path.len() > MAX_PATH {
+        path.drain(..path.len() - MAX_PATH);
+        path.insert(0, "...".to_string());
+    }
+    path
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphErrorKind {
+    /// The node turned finite values into NaN, in its data or in the gradients it sent back.
+    NaN,
+    /// The node was already borrowed, e.g. by a `Ref` held by the caller.
+    BorrowConflict,
+    /// A placeholder was never given a value.
+    MissingPlaceholder,
+    /// The graph is deeper than [`Val::set_max_depth`], the node being the leaf at the bottom
+    /// of its deepest path.
+    TooDeep,
+}
 
-        let node_idx = g.add_node(self.to_string());
-        traverse(self, node_idx, &mut g);
+/// Where a checked run of the graph failed, see [`Val::try_recompute`] and
+/// [`Val::try_back_prop_gradient`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphError {
+    pub kind: GraphErrorKind,
+    pub label: Option<String>,
+    pub op: Option<String>,
+    /// The names of the nodes from the output down to the failing node, the failing node last,
+    /// with only the last few kept.
+    pub path: Vec<String>,
+}
 
-        draw_graph(&g);
+impl Display for GraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} at node", self.kind)?;
+        if let Some(label) = &self.label {
+            write!(f, " '{label}'")?;
+        }
+        if let Some(op) = &self.op {
+            write!(f, " (op {op})")?;
+        }
+        write!(f, ", path: {}", self.path.join(" -> "))
     }
 }
 
+impl std::error::Error for GraphError {}
+
 impl ValInternal {
     fn new(
         data: f64,
         label: Option<String>,
         op: Option<String>,
         prev: Vec<Val>,
+        forward: Option<ComputeForwardFn>,
         propagate: Option<PropagateGradientBackwardsFn>,
     ) -> ValInternal {
         ValInternal {
@@ -161,7 +1606,9 @@ impl ValInternal {
             label,
             operation: op,
             parents: prev,
+            forward,
             propagate,
+            placeholder: false,
         }
     }
 }
@@ -188,11 +1635,14 @@ impl Deref for Val {
 impl std::ops::Add<Val> for Val {
     type Output = Val;
 
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn add(self, other: Val) -> Self::Output {
-        let result = self.borrow().data + other.borrow().data;
+        let _scope = profiler::forward("+");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data() + parents[1].data();
+        let parents = vec![self.clone(), other.clone()];
 
         let prop_fn: PropagateGradientBackwardsFn = |value| {
-            if *value.parents[1].borrow() == *value.parents[0].borrow() {
+            if Rc::ptr_eq(&value.parents[0], &value.parents[1]) {
                 // The both the parent nodes are the same.
                 let mut first = value.parents[0].borrow_mut();
                 first.gradient += 2.0 * value.gradient;
@@ -206,10 +1656,11 @@ impl std::ops::Add<Val> for Val {
         };
 
         Val::with_neuron_internal(ValInternal::new(
-            result,
+            forward_fn(&parents),
             None,
             Some("+".to_string()),
-            vec![self.clone(), other.clone()],
+            parents,
+            Some(forward_fn),
             Some(prop_fn),
         ))
     }
@@ -225,53 +1676,109 @@ impl std::ops::Neg for Val {
 
 impl From<f64> for Val {
     fn from(t: f64) -> Val {
-        Val::with_neuron_internal(ValInternal::new(t, None, None, Vec::new(), None))
+        Val::with_neuron_internal(ValInternal::new(t, None, None, Vec::new(), None, None))
     }
 }
 
-impl std::ops::Mul<Val> for Val {
+impl std::ops::Sub<Val> for Val {
+    type Output = Val;
+
+    fn sub(self, other: Val) -> Self::Output {
+        self + -other
+    }
+}
+
+impl std::ops::Div<Val> for Val {
+    type Output = Val;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, other: Val) -> Self::Output {
+        self * other.recip()
+    }
+}
+
+// Scalars on either side of an op become constant nodes.
+
+impl std::ops::Add<f64> for Val {
+    type Output = Val;
+
+    fn add(self, other: f64) -> Self::Output {
+        self + Val::from(other)
+    }
+}
+
+impl std::ops::Add<Val> for f64 {
+    type Output = Val;
+
+    fn add(self, other: Val) -> Self::Output {
+        Val::from(self) + other
+    }
+}
+
+impl std::ops::Sub<f64> for Val {
+    type Output = Val;
+
+    fn sub(self, other: f64) -> Self::Output {
+        self + Val::from(-other)
+    }
+}
+
+impl std::ops::Sub<Val> for f64 {
+    type Output = Val;
+
+    fn sub(self, other: Val) -> Self::Output {
+        Val::from(self) + -other
+    }
+}
+
+impl std::ops::Mul<f64> for Val {
+    type Output = Val;
+
+    fn mul(self, other: f64) -> Self::Output {
+        self * Val::from(other)
+    }
+}
+
+impl std::ops::Mul<Val> for f64 {
     type Output = Val;
 
     fn mul(self, other: Val) -> Self::Output {
-        &self * other
+        Val::from(self) * other
+    }
+}
 
-        // let result = self.borrow().data * other.borrow().data;
+impl std::ops::Div<f64> for Val {
+    type Output = Val;
 
-        // let prop_fn: PropagateGradientBackwardsFn = |value| {
-        //     if *value.parents[1].borrow() == *value.parents[0].borrow() {
-        //         // The both the parent nodes are the same.
-        //         let mut first = value.parents[0].borrow_mut();
-        //         first.gradient += 2.0 * first.data;
-        //     } else {
-        //         let mut first = value.parents[0].borrow_mut();
-        //         let mut second = value.parents[1].borrow_mut();
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, other: f64) -> Self::Output {
+        self * Val::from(1.0 / other)
+    }
+}
 
-        //         first.gradient += second.data * value.gradient;
-        //         second.gradient += first.data * value.gradient;
-        //     }
-        // };
+impl std::ops::Div<Val> for f64 {
+    type Output = Val;
 
-        // Val::with_neuron_internal(ValInternal::new(
-        //     result,
-        //     None,
-        //     Some("*".to_string()),
-        //     vec![self.clone(), other.clone()],
-        //     Some(prop_fn),
-        // ))
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, other: Val) -> Self::Output {
+        Val::from(self) * other.recip()
     }
 }
 
-impl std::ops::Mul<Val> for &Val {
+impl std::ops::Mul<Val> for Val {
     type Output = Val;
 
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn mul(self, other: Val) -> Self::Output {
-        let result = self.borrow().data * other.borrow().data;
+        let _scope = profiler::forward("*");
+        let forward_fn: ComputeForwardFn = |parents| parents[0].data() * parents[1].data();
+        let parents = vec![self.clone(), other.clone()];
 
         let prop_fn: PropagateGradientBackwardsFn = |value| {
-            if *value.parents[1].borrow() == *value.parents[0].borrow() {
+            if Rc::ptr_eq(&value.parents[0], &value.parents[1]) {
                 // The both the parent nodes are the same.
                 let mut first = value.parents[0].borrow_mut();
-                first.gradient += 2.0 * first.data;
+                first.gradient += 2.0 * first.data * value.gradient;
             } else {
                 let mut first = value.parents[0].borrow_mut();
                 let mut second = value.parents[1].borrow_mut();
@@ -282,15 +1789,126 @@ impl std::ops::Mul<Val> for &Val {
         };
 
         Val::with_neuron_internal(ValInternal::new(
-            result,
+            forward_fn(&parents),
             None,
             Some("*".to_string()),
-            vec![self.clone(), other.clone()],
+            parents,
+            Some(forward_fn),
             Some(prop_fn),
         ))
     }
 }
 
+impl std::ops::Neg for &Val {
+    type Output = Val;
+
+    fn neg(self) -> Self::Output {
+        -self.clone()
+    }
+}
+
+/// Implement `$op` for every mix of owned and borrowed operands, and for borrowed nodes with
+/// scalars, by cloning the borrowed nodes into the owned implementation.
+macro_rules! ref_ops {
+    ($op:ident, $method:ident) => {
+        impl std::ops::$op<&Val> for &Val {
+            type Output = Val;
+
+            fn $method(self, other: &Val) -> Self::Output {
+                std::ops::$op::$method(self.clone(), other.clone())
+            }
+        }
+
+        impl std::ops::$op<Val> for &Val {
+            type Output = Val;
+
+            fn $method(self, other: Val) -> Self::Output {
+                std::ops::$op::$method(self.clone(), other)
+            }
+        }
+
+        impl std::ops::$op<&Val> for Val {
+            type Output = Val;
+
+            fn $method(self, other: &Val) -> Self::Output {
+                std::ops::$op::$method(self, other.clone())
+            }
+        }
+
+        impl std::ops::$op<f64> for &Val {
+            type Output = Val;
+
+            fn $method(self, other: f64) -> Self::Output {
+                std::ops::$op::$method(self.clone(), other)
+            }
+        }
+
+        impl std::ops::$op<&Val> for f64 {
+            type Output = Val;
+
+            fn $method(self, other: &Val) -> Self::Output {
+                std::ops::$op::$method(self, other.clone())
+            }
+        }
+    };
+}
+
+ref_ops!(Add, add);
+ref_ops!(Sub, sub);
+ref_ops!(Mul, mul);
+ref_ops!(Div, div);
+
+/// Implement `$op` for every right hand side `Val` has the binary op for: the node is replaced by
+/// a new one over its previous value, e.g. `x += y` is `x = x + y`. Prefer
+/// [`Val::accumulate`] over `+=` to sum many terms, it keeps the graph flat.
+macro_rules! assign_ops {
+    ($op:ident, $method:ident, $binary:ident, $binary_method:ident) => {
+        impl<T> std::ops::$op<T> for Val
+        where
+            Val: std::ops::$binary<T, Output = Val>,
+        {
+            fn $method(&mut self, other: T) {
+                *self = std::ops::$binary::$binary_method(self.clone(), other);
+            }
+        }
+    };
+}
+
+assign_ops!(AddAssign, add_assign, Add, add);
+assign_ops!(SubAssign, sub_assign, Sub, sub);
+assign_ops!(MulAssign, mul_assign, Mul, mul);
+assign_ops!(DivAssign, div_assign, Div, div);
+
+/// Combine `vals` pairwise, level by level, so the graph is only log2(n) deep.
+fn balanced(mut vals: Vec<Val>, combine: fn(Val, Val) -> Val) -> Option<Val> {
+    while vals.len() > 1 {
+        let mut next = Vec::with_capacity(vals.len().div_ceil(2));
+        let mut rest = vals.into_iter();
+        while let Some(a) = rest.next() {
+            next.push(match rest.next() {
+                Some(b) => combine(a, b),
+                None => a,
+            });
+        }
+        vals = next;
+    }
+    vals.pop()
+}
+
+/// Adds as a balanced tree, 0 for no terms.
+impl std::iter::Sum<Val> for Val {
+    fn sum<I: Iterator<Item = Val>>(iter: I) -> Self {
+        balanced(iter.collect(), |a, b| a + b).unwrap_or_else(|| Val::from(0.0))
+    }
+}
+
+/// Multiplies as a balanced tree, 1 for no factors.
+impl std::iter::Product<Val> for Val {
+    fn product<I: Iterator<Item = Val>>(iter: I) -> Self {
+        balanced(iter.collect(), |a, b| a * b).unwrap_or_else(|| Val::from(1.0))
+    }
+}
+
 impl Display for ValInternal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let label = if let Some(label) = &self.label {
@@ -300,11 +1918,16 @@ impl Display for ValInternal {
         };
 
         let op = if let Some(op) = &self.operation {
-            op
+            display::op(op)
         } else {
-            ""
+            String::new()
         };
-        write!(f, "{label}| op:{op}, v:{}, g:{}", self.data, self.gradient)
+        write!(
+            f,
+            "{label}| op:{op}, v:{}, g:{}",
+            display::number(self.data, None),
+            display::number(self.gradient, None)
+        )
     }
 }
 
@@ -359,6 +1982,160 @@ mod tests {
         l.visualize();
     }
 
+    #[test]
+    fn memory_estimate() {
+        let a = Val::new(2.0, "a");
+        let b = Val::new(-3.0, "b");
+        let e = a.clone() * b;
+        let l = e.clone() + e * a;
+
+        let memory = l.memory_estimate();
+        assert_eq!(memory.nodes, 5);
+        assert!(memory.bytes > 5 * std::mem::size_of::<super::ValInternal>());
+    }
+
+    #[test]
+    fn shared_node_gradient() {
+        // e feeds into l twice, it has to collect both contributions before passing them on.
+        let a = Val::new(2.0, "a");
+        let b = Val::new(-3.0, "b");
+        let e = a.clone() * b.clone();
+        let l = e.clone() + e * Val::new(4.0, "c");
+        l.back_prop_gradient();
+
+        assert_eq!(a.gradient(), 5.0 * -3.0);
+        assert_eq!(b.gradient(), 5.0 * 2.0);
+    }
+
+    #[test]
+    fn back_prop_gradient_to() {
+        let a = Val::new(2.0, "a");
+        let b = Val::new(-3.0, "b");
+        let c = Val::new(10.0, "c");
+        let d = Val::new(-2.0, "d");
+        let l = a.clone() * b.clone() + c.clone() * d.clone();
+
+        let params = [a.clone()];
+        l.back_prop_gradient_to(&params);
+
+        assert_eq!(a.gradient(), -3.0);
+        assert_eq!(c.gradient(), 0.0);
+        assert_eq!(d.gradient(), 0.0);
+        assert_eq!(l.pruned_order(&params).len(), 3);
+    }
+
+    #[test]
+    fn backward_wrt() {
+        let x = Val::new(2.0, "x");
+        let w = Val::new(-3.0, "w");
+        let b = Val::new(1.0, "b");
+        w.set_gradient(0.5);
+
+        let l = (x.clone() * w.clone() + b.clone()).relu() + x.clone() * x.clone();
+        l.backward_wrt(std::slice::from_ref(&x));
+
+        assert_eq!(x.gradient(), 4.0);
+        assert_eq!(w.gradient(), 0.5);
+        assert_eq!(b.gradient(), 0.0);
+        assert_eq!(l.gradient(), 0.0);
+    }
+
+    #[test]
+    fn to_json_graph() {
+        let a = Val::new(2.0, "a");
+        let b = Val::new(-3.0, "\"b\"");
+        let l = (a * b).relu().with_label("L");
+
+        assert_eq!(
+            l.to_json_graph(),
+            concat!(
+                r#"{"output":3,"nodes":["#,
+                r#"{"id":0,"label":"\"b\"","op":null,"data":-3.0,"grad":0.0},"#,
+                r#"{"id":1,"label":"a","op":null,"data":2.0,"grad":0.0},"#,
+                r#"{"id":2,"label":null,"op":"*","data":-6.0,"grad":0.0},"#,
+                r#"{"id":3,"label":"L","op":"ReLU","data":0.0,"grad":0.0}],"#,
+                r#""edges":[{"from":1,"to":2,"position":0},{"from":0,"to":2,"position":1},"#,
+                r#"{"from":2,"to":3,"position":0}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn free_graph() {
+        let a = Val::new(2.0, "a");
+        let b = Val::new(-3.0, "b");
+        let e = a.clone() * b;
+        let l = e.clone() + a.clone();
+        l.back_prop_gradient();
+
+        l.free_graph();
+
+        assert_eq!(l.memory_estimate().nodes, 1);
+        assert_eq!(e.memory_estimate().nodes, 1);
+        assert_eq!(l.data(), -4.0);
+        assert_eq!(a.gradient(), -2.0);
+        assert_eq!(std::rc::Rc::strong_count(&a), 1);
+    }
+
+    #[test]
+    fn placeholders() {
+        let x = Val::placeholder("x");
+        let w = Val::new(3.0, "w");
+        let l = x.clone() * w;
+
+        assert!(x.data().is_nan());
+        assert!(x.is_placeholder());
+        assert_eq!(l.placeholders(), vec![x.clone()]);
+
+        x.set_data(2.0);
+        assert_eq!(x.data(), 2.0);
+    }
+
+    #[test]
+    fn recompute() {
+        let x = Val::placeholder("x");
+        let w = Val::new(3.0, "w");
+        let y = (x.clone() * w.clone() + Val::from(1.0))
+            .relu()
+            .pow(&Val::from(2.0));
+
+        x.set_data(2.0);
+        y.recompute();
+        assert_eq!(y.data(), 49.0);
+
+        x.set_data(-1.0);
+        w.set_data(0.5);
+        y.recompute();
+        assert_eq!(y.data(), 0.25);
+    }
+
+    #[test]
+    fn accumulate() {
+        let xs: Vec<Val> = (0..1000).map(|i| Val::new(i as f64, "x")).collect();
+
+        let mut total = Val::from(0.0);
+        for x in &xs {
+            total.accumulate(x.clone() * x.clone());
+        }
+        total.accumulate(xs[0].clone() + xs[1].clone());
+
+        assert_eq!(
+            total.data(),
+            (0..1000).map(|i| (i * i) as f64).sum::<f64>() + 1.0
+        );
+        // The 1000 products and the sum, their leaves, the accumulator and its initial value.
+        assert_eq!(total.memory_estimate().nodes, 2000 + 1 + 2);
+
+        total.back_prop_gradient();
+        assert_eq!(xs[3].gradient(), 6.0);
+        assert_eq!(xs[1].gradient(), 3.0);
+
+        // A shared accumulator is left alone.
+        let shared = total.clone();
+        total.accumulate(Val::from(1.0));
+        assert_eq!(shared.data() + 1.0, total.data());
+    }
+
     #[test]
     fn add_node_parents_same() {
         let a: Val = Val::new(3.0, "a");
@@ -374,4 +2151,474 @@ mod tests {
         let b = b.with_label("b");
         b.back_prop_gradient();
     }
+
+    #[test]
+    fn tanh() {
+        let x = Val::new(0.5, "x");
+        let y = x.tanh();
+        assert_eq!(y.data(), 0.5f64.tanh());
+
+        y.back_prop_gradient();
+        let expected = crate::testing::numerical_gradient(&y, &x, 1e-6);
+        assert!((x.gradient() - expected).abs() < 1e-8);
+        assert_eq!(x.gradient(), 1.0 - 0.5f64.tanh().powi(2));
+    }
+
+    #[test]
+    fn sin_cos() {
+        let x = Val::new(0.7, "x");
+        let y = x.sin() * x.cos();
+        assert_eq!(y.data(), 0.7f64.sin() * 0.7f64.cos());
+
+        y.back_prop_gradient();
+        let expected = crate::testing::numerical_gradient(&y, &x, 1e-6);
+        assert!((x.gradient() - expected).abs() < 1e-8);
+        // sin(x) cos(x) = sin(2x) / 2
+        assert!((x.gradient() - 1.4f64.cos()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn tree() {
+        let a = Val::new(2.0, "a");
+        let b = Val::new(-3.0, "b");
+        let l = (a.clone() * b + Val::from(1.0)).with_label("L");
+        l.back_prop_gradient();
+
+        let lines = [
+            "+ L (data: -5.0000, grad: 1.0000)",
+            "  * (data: -6.0000, grad: 1.0000)",
+            "    a (data: 2.0000, grad: -3.0000)",
+            "    b (data: -3.0000, grad: 2.0000)",
+            "  const (data: 1.0000, grad: 1.0000)",
+        ];
+        assert_eq!(l.tree(5), lines.join("\n") + "\n");
+        assert_eq!(l.tree(1).lines().nth(2), Some("    ..."));
+    }
+
+    #[test]
+    fn exp() {
+        let x = Val::new(0.5, "x");
+        let y = x.exp() * x.clone();
+        assert_eq!(y.data(), 0.5f64.exp() * 0.5);
+
+        y.back_prop_gradient();
+        let expected = crate::testing::numerical_gradient(&y, &x, 1e-6);
+        assert!((x.gradient() - expected).abs() < 1e-8);
+    }
+
+    #[test]
+    fn ln() {
+        let x = Val::new(4.0, "x");
+        let y = x.ln() + x.log(2.0);
+        assert_eq!(y.data(), 4.0f64.ln() + 2.0);
+
+        y.back_prop_gradient();
+        assert!((x.gradient() - (0.25 + 0.25 / 2.0f64.ln())).abs() < 1e-12);
+        assert!(Val::from(-1.0).ln().data().is_nan());
+    }
+
+    #[test]
+    fn graph_errors() {
+        use super::GraphErrorKind;
+
+        let x = Val::placeholder("x");
+        let h = (x.clone() * Val::from(2.0)).with_label("h");
+        let l = (h.clone().ln() + Val::from(1.0)).with_label("L");
+
+        let error = l.try_recompute().unwrap_err();
+        assert_eq!(error.kind, GraphErrorKind::MissingPlaceholder);
+        assert_eq!(error.path, vec!["L", "ln", "h", "x"]);
+        assert!(l.try_back_prop_gradient().is_err());
+
+        x.set_data(-1.0);
+        let error = l.try_recompute().unwrap_err();
+        assert_eq!(error.kind, GraphErrorKind::NaN);
+        assert_eq!(error.op.as_deref(), Some("ln"));
+        assert_eq!(error.to_string(), "NaN at node (op ln), path: L -> ln");
+
+        x.set_data(1.0);
+        l.try_recompute().unwrap();
+        {
+            let _held = h.borrow();
+            let error = l.try_back_prop_gradient().unwrap_err();
+            assert_eq!(error.kind, GraphErrorKind::BorrowConflict);
+        }
+        l.try_back_prop_gradient().unwrap();
+        assert_eq!(x.gradient(), 1.0);
+
+        // ln receives a zero gradient and sends back 0 / 0.
+        let a = Val::new(0.0, "a");
+        let error = (a.ln() * Val::from(0.0))
+            .try_back_prop_gradient()
+            .unwrap_err();
+        assert_eq!(error.kind, GraphErrorKind::NaN);
+        assert_eq!(error.path, vec!["*", "ln"]);
+    }
+
+    #[test]
+    fn max_depth() {
+        use super::GraphErrorKind;
+
+        let w = Val::new(0.5, "w");
+        let mut h = Val::new(1.0, "h0");
+        for _ in 0..100 {
+            h = (h * w.clone()).tanh().with_label("h");
+        }
+        assert_eq!(h.depth(), 200);
+        assert_eq!(w.depth(), 0);
+        h.check_depth(200).unwrap();
+
+        let error = h.check_depth(199).unwrap_err();
+        assert_eq!(error.kind, GraphErrorKind::TooDeep);
+        assert_eq!(error.label.as_deref(), Some("h0"));
+        assert_eq!(error.path, ["...", "*", "h", "*", "h", "*", "h0"]);
+
+        Val::set_max_depth(Some(10));
+        let too_deep = h.try_back_prop_gradient().unwrap_err();
+        Val::set_max_depth(None);
+        assert_eq!(too_deep, error);
+        h.try_back_prop_gradient().unwrap();
+    }
+
+    #[test]
+    fn sigmoid() {
+        let x = Val::new(0.5, "x");
+        let y = x.sigmoid();
+        assert!((y.data() - 1.0 / (1.0 + (-0.5f64).exp())).abs() < 1e-15);
+        assert_eq!(Val::from(0.0).sigmoid().data(), 0.5);
+        assert_eq!(Val::from(-1000.0).sigmoid().data(), 0.0);
+        assert_eq!(Val::from(1000.0).sigmoid().data(), 1.0);
+
+        y.back_prop_gradient();
+        let expected = crate::testing::numerical_gradient(&y, &x, 1e-6);
+        assert!((x.gradient() - expected).abs() < 1e-8);
+    }
+
+    #[test]
+    fn hard_tanh_and_sigmoid() {
+        let xs = [Val::new(-4.0, "a"), Val::new(0.6, "b"), Val::new(2.0, "c")];
+        let tanh: Vec<f64> = xs.iter().map(|x| x.hard_tanh().data()).collect();
+        assert_eq!(tanh, vec![-1.0, 0.6, 1.0]);
+        let sigmoid: Vec<f64> = xs.iter().map(|x| x.hard_sigmoid().data()).collect();
+        assert_eq!(sigmoid, vec![0.0, 0.6, 2.0 / 6.0 + 0.5]);
+
+        let y: Val = xs.iter().map(|x| x.hard_tanh() + x.hard_sigmoid()).sum();
+        y.back_prop_gradient();
+        let gradients: Vec<f64> = xs.iter().map(Val::gradient).collect();
+        assert_eq!(gradients, vec![0.0, 1.0 + 1.0 / 6.0, 1.0 / 6.0]);
+    }
+
+    #[test]
+    fn op_counts() {
+        let a = Val::new(2.0, "a");
+        let b = Val::new(-3.0, "b");
+        let mut total = Val::from(0.0);
+        total.accumulate(a.clone() * b.clone());
+        total.accumulate(a.clone() * a.clone());
+        total.accumulate(b.relu());
+
+        let counts = total.op_counts();
+        assert_eq!(counts["*"], 2);
+        assert_eq!(counts["ReLU"], 1);
+        assert_eq!(counts["Σ"], 1);
+        // Two products, a ReLU and three additions into the initial 0.
+        assert_eq!(total.flops(), 6);
+    }
+
+    #[test]
+    fn pow_exponent() {
+        let x = Val::new(2.0, "x");
+        let n = Val::new(3.0, "n");
+        let y = x.pow(&n);
+        y.back_prop_gradient();
+        assert_eq!((x.gradient(), n.gradient()), (12.0, 8.0 * 2.0f64.ln()));
+
+        // The same node as base and exponent.
+        let x = Val::new(1.5, "x");
+        let y = x.pow(&x);
+        y.back_prop_gradient();
+        let expected = crate::testing::numerical_gradient(&y, &x, 1e-6);
+        assert!((x.gradient() - expected).abs() < 1e-6);
+
+        let zero = Val::new(0.0, "zero");
+        let n = Val::new(2.0, "n");
+        zero.pow(&n).back_prop_gradient();
+        assert_eq!(n.gradient(), 0.0);
+    }
+
+    #[test]
+    fn powi() {
+        let x = Val::new(-2.0, "x");
+        let y = x.powi(3) + x.powi(0) + x.powi(-1);
+        assert_eq!(y.data(), -8.0 + 1.0 - 0.5);
+
+        y.back_prop_gradient();
+        assert_eq!(x.gradient(), 3.0 * 4.0 - 0.25);
+
+        let zero = Val::new(0.0, "zero");
+        zero.powi(0).back_prop_gradient();
+        assert_eq!(zero.gradient(), 0.0);
+    }
+
+    #[test]
+    fn div_eps() {
+        let x = Val::new(3.0, "x");
+        let y = Val::new(2.0, "y");
+        let z = x.div_eps(&y, 1e-6);
+        assert_eq!(z.data(), 1.5);
+
+        z.back_prop_gradient();
+        assert_eq!(x.gradient(), 0.5);
+        assert_eq!(y.gradient(), -0.75);
+
+        let zero = Val::new(0.0, "zero");
+        let z = x.div_eps(&zero, 1e-3);
+        assert_eq!(z.data(), 3000.0);
+        z.back_prop_gradient();
+        assert_eq!(zero.gradient(), 0.0);
+        assert_eq!(x.div_eps(&Val::from(-1e-9), 1e-3).data(), -3000.0);
+
+        // The same node on both sides.
+        let z = x.div_eps(&x, 1e-6);
+        z.back_prop_gradient();
+        assert_eq!(z.data(), 1.0);
+    }
+
+    #[test]
+    fn scalar_ops() {
+        let x = Val::new(2.0, "x");
+        let y = (x.clone() * 3.0 + 1.0) / 2.0 - 4.0 + 1.0 / x.clone() + 2.0 * x.clone()
+            - (5.0 - x.clone())
+            + (1.0 + x.clone());
+        assert_eq!(y.data(), 3.5 - 4.0 + 0.5 + 4.0 - 3.0 + 3.0);
+
+        y.back_prop_gradient();
+        assert_eq!(x.gradient(), 1.5 - 0.25 + 2.0 + 1.0 + 1.0);
+        assert_eq!((Val::from(6.0) / Val::from(4.0)).data(), 1.5);
+    }
+
+    #[test]
+    fn reference_ops() {
+        let x = Val::new(3.0, "x");
+        let y = Val::new(2.0, "y");
+
+        let z = &x * &y + &x / &y - -&y + (&x - 1.0) * (2.0 * &y) + (x.clone() + &y);
+        assert_eq!(z.data(), 6.0 + 1.5 + 2.0 + 8.0 + 5.0);
+
+        z.back_prop_gradient();
+        assert_eq!(x.gradient(), 2.0 + 0.5 + 4.0 + 1.0);
+        assert_eq!(y.gradient(), 3.0 - 0.75 + 1.0 + 4.0 + 1.0);
+    }
+
+    #[test]
+    fn sum_and_product() {
+        let xs: Vec<Val> = (1..=5).map(|i| Val::new(i as f64, "x")).collect();
+
+        let sum: Val = xs.iter().cloned().sum();
+        assert_eq!(sum.data(), 15.0);
+        // 4 additions over 5 leaves, 3 levels deep rather than 4.
+        assert_eq!(sum.memory_estimate().nodes, 9);
+        // Leaves at depth 3 are indented by 6 spaces.
+        assert!(sum.tree(3).lines().all(|l| !l.starts_with("        ")));
+        assert!(!sum.tree(3).contains("..."));
+
+        let product: Val = xs.iter().cloned().product();
+        assert_eq!(product.data(), 120.0);
+        product.back_prop_gradient();
+        assert_eq!(xs[1].gradient(), 60.0);
+
+        assert_eq!(std::iter::empty::<Val>().sum::<Val>().data(), 0.0);
+        assert_eq!(std::iter::empty::<Val>().product::<Val>().data(), 1.0);
+    }
+
+    #[test]
+    fn assign_ops() {
+        let x = Val::new(3.0, "x");
+        let mut y = x.clone();
+        y *= &x;
+        y += 1.0;
+        y -= x.clone();
+        y /= 2.0;
+        assert_eq!(y.data(), (9.0 + 1.0 - 3.0) / 2.0);
+        // The original node is untouched.
+        assert_eq!(x.data(), 3.0);
+
+        y.back_prop_gradient();
+        assert_eq!(x.gradient(), (6.0 - 1.0) / 2.0);
+    }
+
+    #[test]
+    fn abs() {
+        let xs = [Val::new(-2.0, "a"), Val::new(0.0, "b"), Val::new(3.0, "c")];
+        let y: Val = xs.iter().map(Val::abs).sum();
+        assert_eq!(y.data(), 5.0);
+
+        y.back_prop_gradient();
+        let gradients: Vec<f64> = xs.iter().map(Val::gradient).collect();
+        assert_eq!(gradients, vec![-1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn sign() {
+        let xs = [Val::new(-2.0, "a"), Val::new(0.0, "b"), Val::new(3.0, "c")];
+        let signs: Vec<f64> = xs.iter().map(|x| x.sign().data()).collect();
+        assert_eq!(signs, vec![-1.0, 0.0, 1.0]);
+
+        let y: Val = xs.iter().map(|x| x.sign() * 2.0).sum();
+        y.back_prop_gradient();
+        assert!(xs.iter().all(|x| x.gradient() == 0.0));
+
+        let y: Val = xs.iter().map(|x| x.sign_straight_through() * 2.0).sum();
+        assert_eq!(y.data(), 0.0);
+        y.back_prop_gradient();
+        assert!(xs.iter().all(|x| x.gradient() == 2.0));
+    }
+
+    #[test]
+    fn max_and_min() {
+        let a = Val::new(2.0, "a");
+        let b = Val::new(-3.0, "b");
+        let y = a.max(&b) * 3.0 + a.min(&b) * 5.0;
+        assert_eq!(y.data(), -9.0);
+
+        y.back_prop_gradient();
+        assert_eq!(a.gradient(), 3.0);
+        assert_eq!(b.gradient(), 5.0);
+
+        // Ties go to the first operand, even when both are the same node.
+        let c = Val::new(1.0, "c");
+        let d = Val::new(1.0, "d");
+        let y = c.max(&d) + c.min(&c);
+        y.back_prop_gradient();
+        assert_eq!((c.gradient(), d.gradient()), (2.0, 0.0));
+    }
+
+    #[test]
+    fn clamp() {
+        let xs = [Val::new(-2.0, "a"), Val::new(0.5, "b"), Val::new(3.0, "c")];
+        let ys: Vec<Val> = xs.iter().map(|x| x.clamp(-1.0, 1.0)).collect();
+        assert_eq!(
+            ys.iter().map(Val::data).collect::<Vec<_>>(),
+            vec![-1.0, 0.5, 1.0]
+        );
+
+        let y: Val = ys.into_iter().sum();
+        y.back_prop_gradient();
+        let gradients: Vec<f64> = xs.iter().map(Val::gradient).collect();
+        assert_eq!(gradients, vec![0.0, 1.0, 0.0]);
+
+        xs[0].set_data(0.25);
+        y.recompute();
+        assert_eq!(y.data(), 1.75);
+    }
+
+    #[test]
+    fn sqrt() {
+        let a = Val::new(3.0, "a");
+        let b = Val::new(4.0, "b");
+        let distance = (a.powi(2) + b.powi(2)).sqrt();
+        assert_eq!(distance.data(), 5.0);
+
+        distance.back_prop_gradient();
+        for x in [&a, &b] {
+            let expected = crate::testing::numerical_gradient(&distance, x, 1e-6);
+            assert!((x.gradient() - expected).abs() < 1e-6);
+        }
+        assert!((a.gradient() - 0.6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn recip() {
+        let x = Val::new(-4.0, "x");
+        let y = x.recip();
+        assert_eq!(y.data(), -0.25);
+
+        y.back_prop_gradient();
+        assert_eq!(x.gradient(), -1.0 / 16.0);
+        let expected = crate::testing::numerical_gradient(&y, &x, 1e-6);
+        assert!((x.gradient() - expected).abs() < 1e-8);
+    }
+
+    #[test]
+    fn leaky_relu() {
+        let a = Val::new(-2.0, "a");
+        let b = Val::new(3.0, "b");
+        let y = a.leaky_relu(0.1) + b.leaky_relu(0.1);
+        assert!((y.data() - 2.8).abs() < 1e-12);
+
+        y.back_prop_gradient();
+        assert_eq!((a.gradient(), b.gradient()), (0.1, 1.0));
+    }
+
+    #[test]
+    fn elu() {
+        let x = Val::new(-0.7, "x");
+        let y = x.elu(1.5);
+        assert!((y.data() - 1.5 * ((-0.7f64).exp() - 1.0)).abs() < 1e-12);
+
+        y.back_prop_gradient();
+        let expected = crate::testing::numerical_gradient(&y, &x, 1e-6);
+        assert!((x.gradient() - expected).abs() < 1e-6);
+
+        let x = Val::new(2.0, "x");
+        let y = x.elu(1.5);
+        y.back_prop_gradient();
+        assert_eq!((y.data(), x.gradient()), (2.0, 1.0));
+    }
+
+    #[test]
+    fn gelu() {
+        assert_eq!(Val::from(0.0).gelu().data(), 0.0);
+        assert!((Val::from(1.0).gelu().data() - 0.8412).abs() < 1e-4);
+
+        for x in [-2.0, -0.3, 0.5, 3.0] {
+            let x = Val::new(x, "x");
+            let y = x.gelu();
+            y.back_prop_gradient();
+            let expected = crate::testing::numerical_gradient(&y, &x, 1e-6);
+            assert!((x.gradient() - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn silu() {
+        assert_eq!(Val::from(0.0).silu().data(), 0.0);
+        assert!((Val::from(-800.0).silu().data()).abs() < 1e-12);
+
+        for x in [-3.0, -0.5, 0.7, 4.0] {
+            let x = Val::new(x, "x");
+            let y = x.silu();
+            y.back_prop_gradient();
+            let expected = crate::testing::numerical_gradient(&y, &x, 1e-6);
+            assert!((x.gradient() - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn mish() {
+        assert_eq!(Val::from(0.0).mish().data(), 0.0);
+        assert_eq!(Val::from(1000.0).mish().data(), 1000.0);
+        assert!((Val::from(1.0).mish().data() - 0.8651).abs() < 1e-4);
+
+        for x in [-3.0, -0.5, 0.7, 4.0] {
+            let x = Val::new(x, "x");
+            let y = x.mish();
+            y.back_prop_gradient();
+            let expected = crate::testing::numerical_gradient(&y, &x, 1e-6);
+            assert!((x.gradient() - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn prelu() {
+        let a = Val::new(-2.0, "a");
+        let b = Val::new(3.0, "b");
+        let slope = Val::new(0.25, "slope");
+        let y = a.prelu(&slope) + b.prelu(&slope);
+        assert_eq!(y.data(), 2.5);
+
+        y.back_prop_gradient();
+        assert_eq!((a.gradient(), b.gradient()), (0.25, 1.0));
+        assert_eq!(slope.gradient(), -2.0);
+    }
 }